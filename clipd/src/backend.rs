@@ -0,0 +1,96 @@
+//! The desktop clipboard clipd mirrors. User copies are pushed to it, and its
+//! own changes come back in as `CopySource::Wayland` copies. Exactly one
+//! backend is active, picked at startup.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Result;
+use clipto_ipc::Status;
+#[cfg(target_os = "linux")]
+use serde::Deserialize;
+
+use crate::config::Config;
+#[cfg(target_os = "macos")]
+use crate::macos::Macos;
+#[cfg(target_os = "linux")]
+use crate::portal::{self, Portal};
+#[cfg(target_os = "linux")]
+use crate::wayland::Wayland;
+#[cfg(target_os = "linux")]
+use crate::wsl::{self, Wsl};
+use crate::Daemon;
+
+pub trait Backend: Send + Sync {
+    /// Recorded as the client of entries synced in from this backend.
+    fn name(&self) -> &'static str;
+
+    /// Make `payload` the desktop clipboard. `Ok(false)` if the backend isn't
+    /// reachable right now (or can't carry this type) and nothing was done.
+    fn set(&self, payload: &[u8], mime: Option<&str>) -> Result<bool>;
+
+    /// Offer `payload` for a single paste, then call `done` once the desktop
+    /// clipboard has let go of it: it was pasted, or something replaced it.
+    /// `Ok(false)` if this backend can't limit pastes; the entry then stays
+    /// out of the desktop clipboard.
+    fn set_once(
+        &self,
+        payload: &[u8],
+        mime: Option<&str>,
+        done: Box<dyn FnOnce() + Send>,
+    ) -> Result<bool> {
+        let _ = (payload, mime, done);
+        Ok(false)
+    }
+
+    /// Empty the desktop clipboard. A no-op when it isn't reachable.
+    fn clear(&self) -> Result<()>;
+
+    /// Keep `payload` in the desktop clipboard after clipd exits.
+    fn persist(&self, payload: &[u8], mime: Option<&str>) -> Result<()>;
+
+    /// Start feeding desktop clipboard changes to `daemon`.
+    fn start_watcher(&self, daemon: Arc<Daemon>);
+
+    /// For `clipto status`.
+    fn status(&self) -> Status;
+
+    /// Programs this backend runs, by name or path, for the sandbox.
+    #[cfg(target_os = "linux")]
+    fn programs(&self) -> Vec<PathBuf>;
+}
+
+/// Which backend to use, from the config's `backend` key.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// WSL when running under it, else the portal under GNOME, else Wayland.
+    #[default]
+    Auto,
+    /// `wl-copy` and `wl-paste --watch`.
+    Wayland,
+    /// xdg-desktop-portal's `Clipboard` interface.
+    Portal,
+}
+
+/// The configured backend. Auto-detection picks WSL when running under it,
+/// even if WSLg also provides a compositor; the portal under GNOME, whose
+/// compositor `wl-paste --watch` can't follow; Wayland otherwise.
+#[cfg(target_os = "linux")]
+pub fn detect(config: &Config, clipto_bin: PathBuf) -> Box<dyn Backend> {
+    match config.backend {
+        BackendKind::Auto if wsl::detected() => Box::new(Wsl::new(config.wsl.clone())),
+        BackendKind::Auto if portal::preferred() => Box::new(Portal::new(config.watch.clone())),
+        BackendKind::Portal => Box::new(Portal::new(config.watch.clone())),
+        BackendKind::Auto | BackendKind::Wayland => {
+            Box::new(Wayland::new(clipto_bin, config.watch.clone(), &config.x11))
+        }
+    }
+}
+
+/// The general pasteboard.
+#[cfg(target_os = "macos")]
+pub fn detect(config: &Config, _clipto_bin: PathBuf) -> Box<dyn Backend> {
+    Box::new(Macos::new(config.macos.clone()))
+}
@@ -0,0 +1,79 @@
+//! Queries about the focused window, via the compositor's own IPC CLI.
+//! Hyprland (`hyprctl`) and sway (`swaymsg`) are supported; anything else
+//! reports nothing and never suppresses.
+
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// The compositor CLIs queried, for the sandbox.
+#[cfg(target_os = "linux")]
+pub const PROGRAMS: &[&str] = &["hyprctl", "swaymsg"];
+
+/// What the focused window is doing right now.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Focus {
+    pub fullscreen: bool,
+    pub idle_inhibit: bool,
+}
+
+/// Inspect the focused window. `None` if no supported compositor is running
+/// or the query failed.
+pub fn focused() -> Option<Focus> {
+    let result = if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        hyprland()
+    } else if std::env::var_os("SWAYSOCK").is_some() {
+        sway()
+    } else {
+        return None;
+    };
+
+    match result {
+        Ok(focus) => Some(focus),
+        Err(e) => {
+            tracing::debug!("compositor query failed: {e:#}");
+            None
+        }
+    }
+}
+
+fn query(program: &str, args: &[&str]) -> Result<Value> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run {program}"))?;
+    serde_json::from_slice(&output.stdout).with_context(|| format!("{program}: invalid JSON"))
+}
+
+/// `fullscreen` is a bool in older Hyprland releases and a mode number
+/// (0 = none) in newer ones. Hyprland doesn't report idle inhibitors.
+fn hyprland() -> Result<Focus> {
+    let window = query("hyprctl", &["activewindow", "-j"])?;
+    let fullscreen = match &window["fullscreen"] {
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_u64().is_some_and(|n| n != 0),
+        _ => false,
+    };
+    Ok(Focus { fullscreen, idle_inhibit: false })
+}
+
+fn sway() -> Result<Focus> {
+    let tree = query("swaymsg", &["-t", "get_tree", "-r"])?;
+    let node = find_focused(&tree).context("no focused node")?;
+    Ok(Focus {
+        fullscreen: node["fullscreen_mode"].as_u64().is_some_and(|m| m != 0),
+        idle_inhibit: node["inhibit_idle"].as_bool().unwrap_or(false),
+    })
+}
+
+fn find_focused(node: &Value) -> Option<&Value> {
+    if node["focused"].as_bool() == Some(true) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(find_focused)
+}
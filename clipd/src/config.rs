@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clipto_ipc::{NamespaceConfig, PasteConfig};
+use serde::Deserialize;
+
+#[cfg(target_os = "linux")]
+use crate::backend::BackendKind;
+use crate::history::HistoryConfig;
+use crate::logfile::RotationConfig;
+#[cfg(target_os = "macos")]
+use crate::macos::MacosConfig;
+use crate::notify::NotifyConfig;
+use crate::ratelimit::RateLimitConfig;
+#[cfg(target_os = "linux")]
+use crate::sandbox::SandboxConfig;
+#[cfg(target_os = "linux")]
+use crate::wsl::WslConfig;
+#[cfg(target_os = "linux")]
+use crate::x11::X11Config;
+
+/// Daemon configuration, read once at startup from
+/// `$XDG_CONFIG_HOME/clipto/config.toml` (or `$CLIPTO_CONFIG`). Every key is
+/// optional; a missing file is the same as an empty one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Default `tracing` filter directive, e.g. `"info"` or `"clipd=debug"`.
+    /// `RUST_LOG` takes precedence when set.
+    pub log_level: Option<String>,
+    /// Socket to listen on: a path, or `@name` for the abstract namespace.
+    /// Clients read this key too; `CLIPTO_SOCKET` overrides it for both.
+    pub socket: Option<String>,
+    /// Client-side paste behavior (`[paste]` table); only validated here.
+    pub paste: PasteConfig,
+    /// Client-side namespace defaults (`[namespace]` table); only validated
+    /// here.
+    pub namespace: NamespaceConfig,
+    /// Log to this file instead of journald/stderr, rotating it as
+    /// `[log_rotation]` says.
+    pub log_file: Option<PathBuf>,
+    /// Size/age limits and retention for `log_file` (`[log_rotation]` table).
+    pub log_rotation: RotationConfig,
+    /// Write daemon counters to this path in the Prometheus text format, for
+    /// node_exporter's textfile collector.
+    pub stats_textfile: Option<PathBuf>,
+    /// How often to rewrite `stats_textfile`, in seconds. Defaults to 15.
+    pub stats_interval_secs: Option<u64>,
+    /// Also serve the plain-text line protocol on
+    /// `$XDG_RUNTIME_DIR/clipto-line.sock`, for editor integrations.
+    pub line_socket: bool,
+    /// On clean shutdown, leave the current entry in the compositor clipboard
+    /// by handing it to a detached `wl-copy`.
+    pub persist_on_exit: bool,
+    /// Seconds before a `clipto paste --as-file` file is deleted. Defaults to
+    /// 300.
+    pub paste_file_ttl_secs: Option<u64>,
+    /// Longest request a client may send, in bytes; longer ones are refused
+    /// before any of it is read. Defaults to 256 MiB.
+    pub max_request_bytes: Option<u64>,
+    /// Seconds one read or write on a client connection may block before the
+    /// daemon gives up on it. Defaults to 10.
+    pub io_timeout_secs: Option<u64>,
+    /// Answer reads that are refused (throttled) or find nothing with one
+    /// fixed reply after a fixed delay, so a prober can't tell which.
+    pub uniform_denials: bool,
+    /// Serve the `dev.clipto.Daemon` interface on the session bus.
+    pub dbus: bool,
+    /// Also claim `org.kde.klipper` and serve Klipper's D-Bus interface.
+    pub klipper: bool,
+    /// Wipe the clipboard (ours and the compositor's) when logind reports
+    /// that one of this user's sessions locked.
+    pub wipe_on_lock: bool,
+    /// Wipe the clipboard when the system is about to suspend.
+    pub wipe_on_sleep: bool,
+    /// History size and per-source quotas (`[history]` table).
+    pub history: HistoryConfig,
+    /// Desktop notifications (`[notifications]` table).
+    pub notifications: NotifyConfig,
+    /// Per-client request limits (`[rate_limit]` table); off by default.
+    pub rate_limit: RateLimitConfig,
+    /// Which desktop clipboard to mirror: `auto`, `wayland` or `portal`.
+    #[cfg(target_os = "linux")]
+    pub backend: BackendKind,
+    /// Which MIME types to take from the compositor clipboard (`[watch]`
+    /// table).
+    pub watch: WatchConfig,
+    /// Windows clipboard polling under WSL (`[wsl]` table).
+    #[cfg(target_os = "linux")]
+    pub wsl: WslConfig,
+    /// Pushing user copies to the X11 CLIPBOARD selection too (`[x11]`
+    /// table).
+    #[cfg(target_os = "linux")]
+    pub x11: X11Config,
+    /// Pasteboard polling (`[macos]` table).
+    #[cfg(target_os = "macos")]
+    pub macos: MacosConfig,
+    /// Landlock and seccomp confinement (`[sandbox]` table).
+    #[cfg(target_os = "linux")]
+    pub sandbox: SandboxConfig,
+}
+
+/// MIME type preferences for the Wayland watcher. Patterns are exact types
+/// (`text/plain;charset=utf-8`), a major type (`image/*`), or `*`.
+///
+/// The first offered type matching a `prefer` pattern, in pattern order, is
+/// fetched. Failing that, the first offered type not matching `deny` is.
+/// `prefer` beats `deny`, so `deny = ["*"]` turns `prefer` into an allowlist.
+/// The default prefers UTF-8 text, then any plain text, then PNG images. With
+/// both set empty, `wl-paste` picks the type itself and it isn't recorded.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    pub prefer: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        let prefer = ["text/plain;charset=utf-8", "text/plain", "image/png"];
+        Self { prefer: prefer.map(String::from).to_vec(), deny: Vec::new() }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let Some(path) = clipto_ipc::config_path() else {
+            return Ok(Self::default());
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+
+        let config: Self = toml::from_str(&text)
+            .with_context(|| format!("invalid config in {}", path.display()))?;
+        config
+            .rate_limit
+            .validate()
+            .with_context(|| format!("invalid config in {}", path.display()))?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,196 @@
+//! `dev.clipto.Daemon` on the session bus, for GUI tools and scripting
+//! languages that would rather not speak the bincode framing protocol. The
+//! same connection optionally serves the Klipper interface (see `klipper`).
+//!
+//! ```text
+//! Copy(ay payload)
+//! Paste() -> ay
+//! Clear()
+//! History() -> a(st)    source and size of each entry, newest first,
+//!                       leaving out paste-once entries
+//! Get(u index) -> ay    decrypt one history entry (a paste-once one is
+//!                       destroyed by it)
+//! signal Changed()
+//! ```
+//!
+//! Calls are rate-limited like socket requests, with the caller's PID and UID
+//! asked of the bus. A throttled call fails with `LimitsExceeded`, except
+//! that under `uniform_denials` a throttled read fails like one that found
+//! nothing.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use tracing::{error, info, info_span};
+use zbus::blocking::{connection, Connection};
+use zbus::message::Header;
+use zbus::names::BusName;
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, interface};
+
+use clipto_ipc::{CopySource, Request, Response};
+
+use crate::klipper::{self, Klipper};
+use crate::{answer, throttle_peer, Daemon};
+
+const NAME: &str = "dev.clipto.Daemon";
+const PATH: &str = "/dev/clipto/Daemon";
+
+struct DaemonInterface {
+    daemon: Arc<Daemon>,
+}
+
+/// Errors as `Failed`, and throttling as `LimitsExceeded`.
+pub fn into_result(response: Response) -> fdo::Result<Response> {
+    match response {
+        Response::Error { message } => Err(fdo::Error::Failed(message)),
+        Response::Throttled { retry_after_ms } => {
+            Err(fdo::Error::LimitsExceeded(format!("throttled; retry in {retry_after_ms}ms")))
+        }
+        other => Ok(other),
+    }
+}
+
+/// `Response::Throttled` if the sender of `header`'s call is over its rate
+/// limit, taking one of its tokens otherwise. Goes to `answer` with the
+/// call's request.
+pub async fn throttle(
+    daemon: &Daemon,
+    conn: &zbus::Connection,
+    header: &Header<'_>,
+) -> fdo::Result<Option<Response>> {
+    if !daemon.limiter.enabled() {
+        return Ok(None);
+    }
+    let Some(sender) = header.sender() else {
+        return Ok(None); // a peer-to-peer connection; there is no bus to ask
+    };
+    let bus = fdo::DBusProxy::new(conn).await?;
+    let sender = BusName::Unique(sender.clone());
+    let pid = bus.get_connection_unix_process_id(sender.clone()).await?;
+    let uid = bus.get_connection_unix_user(sender).await?;
+    Ok(throttle_peer(daemon, pid as libc::pid_t, uid))
+}
+
+#[interface(name = "dev.clipto.Daemon")]
+impl DaemonInterface {
+    async fn copy(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        payload: Vec<u8>,
+    ) -> fdo::Result<()> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        let request = Request::Copy { payload, source: CopySource::User, mime: None, once: false };
+        into_result(answer(&self.daemon, request, "dbus", throttled))?;
+        Ok(())
+    }
+
+    async fn paste(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<Vec<u8>> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        match into_result(answer(&self.daemon, Request::Paste, "dbus", throttled))? {
+            Response::Payload { data } => Ok(data),
+            _ => Err(fdo::Error::Failed("unexpected response to Paste".into())),
+        }
+    }
+
+    async fn clear(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        into_result(answer(&self.daemon, Request::Clear, "dbus", throttled))?;
+        Ok(())
+    }
+
+    async fn history(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<Vec<(String, u64)>> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        match into_result(answer(&self.daemon, Request::History, "dbus", throttled))? {
+            Response::History(entries) => Ok(entries
+                .into_iter()
+                .filter(|e| !e.once)
+                .map(|e| (e.source.to_string(), e.size))
+                .collect()),
+            _ => Err(fdo::Error::Failed("unexpected response to History".into())),
+        }
+    }
+
+    async fn get(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(header)] header: Header<'_>,
+        index: u32,
+    ) -> fdo::Result<Vec<u8>> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        match into_result(answer(&self.daemon, Request::Entry { index }, "dbus", throttled))? {
+            Response::Payload { data } => Ok(data),
+            _ => Err(fdo::Error::Failed("unexpected response to Entry".into())),
+        }
+    }
+
+    #[zbus(signal)]
+    async fn changed(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+/// Claim the enabled interfaces' names and relay clipboard changes as
+/// signals. Failure to reach the bus is logged, not fatal.
+pub fn start(daemon: Arc<Daemon>, native: bool, klipper: bool) {
+    std::thread::spawn(move || {
+        let _span = info_span!("dbus").entered();
+        if let Err(e) = run(daemon, native, klipper) {
+            error!("D-Bus interface stopped: {e:#}");
+        }
+    });
+}
+
+fn run(daemon: Arc<Daemon>, native: bool, klipper: bool) -> Result<()> {
+    let changes = daemon.changes.subscribe();
+
+    let mut builder = connection::Builder::session()?;
+    if native {
+        let iface = DaemonInterface { daemon: Arc::clone(&daemon) };
+        builder = builder.name(NAME)?.serve_at(PATH, iface)?;
+    }
+    if klipper {
+        builder = builder
+            .name(klipper::NAME)?
+            .serve_at(klipper::PATH, Klipper { daemon })?;
+    }
+    let conn: Connection = builder
+        .build()
+        .context("failed to claim D-Bus names on the session bus")?;
+
+    if native {
+        info!("serving {NAME} at {PATH}");
+    }
+    if klipper {
+        info!("serving {} at {}", klipper::NAME, klipper::PATH);
+    }
+
+    for () in changes {
+        if native {
+            conn.emit_signal(None::<()>, PATH, NAME, "Changed", &())
+                .context("failed to emit Changed")?;
+        }
+        if klipper {
+            conn.emit_signal(
+                None::<()>,
+                klipper::PATH,
+                klipper::INTERFACE,
+                "clipboardHistoryUpdated",
+                &(),
+            )
+            .context("failed to emit clipboardHistoryUpdated")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,90 @@
+//! Ring buffer of the most recent warnings and errors, retrievable with
+//! `clipto errors` even when journald logging was never set up or was
+//! filtered too aggressively to show them.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use clipto_ipc::ErrorRecord;
+
+/// How many records to keep.
+const CAPACITY: usize = 64;
+
+#[derive(Default)]
+pub struct ErrorLog {
+    records: Mutex<VecDeque<ErrorRecord>>,
+}
+
+impl ErrorLog {
+    /// Oldest first.
+    pub fn snapshot(&self) -> Vec<ErrorRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn push(&self, record: ErrorRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() == CAPACITY {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+}
+
+/// `tracing` layer feeding an `ErrorLog`. Install it with a `WARN` filter.
+pub struct ErrorLogLayer(pub Arc<ErrorLog>);
+
+impl<S: Subscriber> Layer<S> for ErrorLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let unix_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        self.0.push(ErrorRecord {
+            unix_time,
+            level: event.metadata().level().to_string(),
+            target: event.metadata().target().to_owned(),
+            message: visitor.message,
+        });
+    }
+}
+
+/// Flattens an event into `message key=value …`.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.message.is_empty() {
+            self.message.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.message, "{}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            if !self.message.is_empty() {
+                self.message.push(' ');
+            }
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+/// Fan-out of "the clipboard changed" events to any number of listeners
+/// (D-Bus signal emitter, subscribed clients).
+#[derive(Default)]
+pub struct Changes {
+    subscribers: Mutex<Vec<Sender<()>>>,
+}
+
+impl Changes {
+    pub fn subscribe(&self) -> Receiver<()> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Wake every listener, forgetting those that hung up.
+    pub fn publish(&self) {
+        self.subscribers.lock().unwrap().retain(|tx| tx.send(()).is_ok());
+    }
+}
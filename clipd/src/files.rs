@@ -0,0 +1,88 @@
+//! Short-lived files for integrations that need a path instead of bytes
+//! (`clipto paste --as-file`). They live in `clipto/files` under the runtime
+//! directory (`$XDG_RUNTIME_DIR`, tmpfs on systemd systems), and are deleted
+//! after a TTL.
+
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use tracing::{debug, warn};
+
+pub struct PasteFiles {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl PasteFiles {
+    pub fn new(ttl: Duration) -> Result<Self> {
+        let dir = clipto_ipc::runtime_dir()?.join("clipto").join("files");
+        Ok(Self { dir, ttl })
+    }
+
+    /// Write `data` to a fresh owner-only file and return its path.
+    pub fn write(&self, data: &[u8], extension: &str) -> Result<PathBuf> {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+
+        let name = format!("{:016x}{:016x}.{extension}", rand::random::<u64>(), rand::random::<u64>());
+        let path = self.dir.join(name);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        file.write_all(data)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Delete files older than the TTL, or every file when `all` is set.
+    pub fn collect(&self, all: bool) {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("paste files: {e}");
+                return;
+            }
+        };
+
+        let now = SystemTime::now();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let expired = all || is_expired(&path, now, self.ttl);
+            if expired {
+                match std::fs::remove_file(&path) {
+                    Ok(()) => debug!(path = %path.display(), "paste file removed"),
+                    Err(e) => warn!("failed to remove {}: {e}", path.display()),
+                }
+            }
+        }
+    }
+
+    /// Spawn the garbage collector. It wakes at a fraction of the TTL so files
+    /// never outlive it by much.
+    pub fn start_collector(self: &std::sync::Arc<Self>) {
+        let files = std::sync::Arc::clone(self);
+        let interval = (self.ttl / 4).clamp(Duration::from_secs(1), Duration::from_secs(60));
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            files.collect(false);
+        });
+    }
+}
+
+fn is_expired(path: &Path, now: SystemTime, ttl: Duration) -> bool {
+    path.metadata()
+        .and_then(|m| m.modified())
+        .map(|modified| now.duration_since(modified).unwrap_or_default() >= ttl)
+        .unwrap_or(true)
+}
@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use zeroize::Zeroize;
+
+use clipto_ipc::{display_char, CopySource};
+
+/// `[history]` config table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Total entries kept, newest first. `1` disables history.
+    pub max_entries: usize,
+    /// Per-source caps, applied before `max_entries`.
+    pub quota: Quota,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { max_entries: 20, quota: Quota::default() }
+    }
+}
+
+/// Maximum entries a single source may occupy. Unset means no cap beyond
+/// `max_entries`, so a chatty source can't wash out deliberate copies.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    pub user: Option<usize>,
+    pub wayland: Option<usize>,
+}
+
+impl Quota {
+    fn limit(&self, source: CopySource) -> Option<usize> {
+        match source {
+            CopySource::User => self.user,
+            CopySource::Wayland => self.wayland,
+        }
+    }
+}
+
+// ─── encrypted in-memory buffer ──────────────────────────────────────────────
+
+pub struct EncryptedBuffer {
+    pub nonce: [u8; 12],
+    pub ciphertext: Vec<u8>,
+    pub source: CopySource,
+    /// Content type given at copy time; offered to the compositor on sync.
+    pub mime: Option<String>,
+    pub stored: SystemTime,
+    /// See `HistoryEntry::client`.
+    pub client: String,
+    /// Exempt from quota and `max_entries` eviction; listed first.
+    pub pinned: bool,
+    /// Destroyed after its first paste (`clipto copy --once`).
+    pub once: bool,
+}
+
+impl EncryptedBuffer {
+    /// Plaintext length: the ciphertext minus the 16-byte Poly1305 tag.
+    pub fn plaintext_len(&self) -> usize {
+        self.ciphertext.len().saturating_sub(16)
+    }
+}
+
+impl Drop for EncryptedBuffer {
+    fn drop(&mut self) {
+        self.ciphertext.zeroize();
+    }
+}
+
+// ─── history ──────────────────────────────────────────────────────────────────
+
+/// Encrypted entries, newest first. Index 0 is the current clipboard. A
+/// paste-once entry only ever lives there: whatever replaces it destroys it.
+pub struct History {
+    entries: VecDeque<EncryptedBuffer>,
+    config: HistoryConfig,
+}
+
+impl History {
+    pub fn new(config: HistoryConfig) -> Self {
+        Self { entries: VecDeque::new(), config }
+    }
+
+    pub fn push(&mut self, entry: EncryptedBuffer) {
+        let source = entry.source;
+        self.entries.push_front(entry);
+        self.drop_once();
+
+        if let Some(limit) = self.config.quota.limit(source) {
+            self.evict(limit.max(1), |e| e.source == source);
+        }
+        self.evict(self.config.max_entries.max(1), |_| true);
+    }
+
+    /// Drop the oldest unpinned entries matching `filter` until at most
+    /// `limit` remain. Pinned entries neither count nor get evicted, and the
+    /// entry just pushed (index 0) always survives.
+    fn evict(&mut self, limit: usize, filter: impl Fn(&EncryptedBuffer) -> bool) {
+        let evictable = |e: &EncryptedBuffer| !e.pinned && filter(e);
+        while self.entries.iter().filter(|e| evictable(e)).count() > limit {
+            match self.entries.iter().rposition(evictable) {
+                Some(i) if i > 0 => drop(self.entries.remove(i)),
+                _ => break,
+            }
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&EncryptedBuffer> {
+        self.entries.get(index)
+    }
+
+    /// Move entry `index` to the front, making it the current clipboard.
+    pub fn promote(&mut self, index: usize) -> bool {
+        match self.entries.remove(index) {
+            Some(entry) => {
+                self.entries.push_front(entry);
+                self.drop_once();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop paste-once entries that are no longer the current clipboard.
+    fn drop_once(&mut self) {
+        let mut i = 0;
+        self.entries.retain(|e| {
+            i += 1;
+            i == 1 || !e.once
+        });
+    }
+
+    /// Pin or unpin entry `index`. Returns false if there is no such entry.
+    pub fn set_pinned(&mut self, index: usize, pinned: bool) -> bool {
+        match self.entries.get_mut(index) {
+            Some(entry) => {
+                entry.pinned = pinned;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the entry sealed with `nonce`, wherever it has moved to.
+    /// Returns false if it is already gone.
+    pub fn remove(&mut self, nonce: &[u8; 12]) -> bool {
+        match self.entries.iter().position(|e| &e.nonce == nonce) {
+            Some(i) => {
+                drop(self.entries.remove(i));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &EncryptedBuffer> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// ─── previews ─────────────────────────────────────────────────────────────────
+
+/// A single-line, terminal-safe summary of `data`, at most `max_chars` long.
+/// Characters go through `clipto_ipc::display_char`, so a preview can't drive
+/// or reorder the terminal it is printed to. Images are summarized by type
+/// and size.
+pub fn preview(data: &[u8], mime: Option<&str>, max_chars: usize) -> String {
+    if let Some(mime) = mime.filter(|m| m.starts_with("image/")) {
+        return format!("[{mime}, {} bytes]", data.len());
+    }
+    let Ok(text) = std::str::from_utf8(data) else {
+        return format!("[binary, {} bytes]", data.len());
+    };
+
+    let mut out = String::new();
+    for (i, c) in text.trim().chars().enumerate() {
+        if i == max_chars {
+            out.push('…');
+            break;
+        }
+        out.push(display_char(c));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An entry told apart by `tag`, its nonce's first byte.
+    fn entry(tag: u8, source: CopySource) -> EncryptedBuffer {
+        EncryptedBuffer {
+            nonce: [tag; 12],
+            ciphertext: vec![0; 16],
+            source,
+            mime: None,
+            stored: SystemTime::now(),
+            client: "test".to_owned(),
+            pinned: false,
+            once: false,
+        }
+    }
+
+    fn tags(history: &History) -> Vec<u8> {
+        history.iter().map(|e| e.nonce[0]).collect()
+    }
+
+    fn history(max_entries: usize, user: Option<usize>, wayland: Option<usize>) -> History {
+        History::new(HistoryConfig { max_entries, quota: Quota { user, wayland } })
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted() {
+        let mut h = history(3, None, None);
+        for tag in 1..=5 {
+            h.push(entry(tag, CopySource::User));
+        }
+        assert_eq!(tags(&h), [5, 4, 3]);
+    }
+
+    #[test]
+    fn zero_max_entries_keeps_the_current_clipboard() {
+        let mut h = history(0, None, None);
+        h.push(entry(1, CopySource::User));
+        h.push(entry(2, CopySource::User));
+        assert_eq!(tags(&h), [2]);
+    }
+
+    #[test]
+    fn quota_evicts_only_its_own_source() {
+        let mut h = history(10, None, Some(2));
+        h.push(entry(1, CopySource::User));
+        for tag in 2..=5 {
+            h.push(entry(tag, CopySource::Wayland));
+        }
+        h.push(entry(6, CopySource::User));
+        assert_eq!(tags(&h), [6, 5, 4, 1]);
+    }
+
+    #[test]
+    fn quota_and_max_entries_both_apply() {
+        let mut h = history(3, Some(1), None);
+        h.push(entry(1, CopySource::Wayland));
+        h.push(entry(2, CopySource::User));
+        h.push(entry(3, CopySource::Wayland));
+        h.push(entry(4, CopySource::User));
+        assert_eq!(tags(&h), [4, 3, 1]);
+        h.push(entry(5, CopySource::Wayland));
+        assert_eq!(tags(&h), [5, 4, 3]);
+    }
+
+    fn pinned(tag: u8, source: CopySource) -> EncryptedBuffer {
+        let mut entry = entry(tag, source);
+        entry.pinned = true;
+        entry
+    }
+
+    #[test]
+    fn pinned_entries_are_never_evicted() {
+        let mut h = history(2, None, None);
+        h.push(pinned(1, CopySource::User));
+        h.push(pinned(2, CopySource::User));
+        for tag in 3..=6 {
+            h.push(entry(tag, CopySource::User));
+        }
+        assert_eq!(tags(&h), [6, 5, 2, 1]);
+    }
+
+    #[test]
+    fn pinned_entries_dont_count_against_quota() {
+        let mut h = history(10, None, Some(1));
+        h.push(pinned(1, CopySource::Wayland));
+        h.push(entry(2, CopySource::Wayland));
+        h.push(entry(3, CopySource::Wayland));
+        assert_eq!(tags(&h), [3, 1]);
+    }
+
+    #[test]
+    fn unpinned_entry_is_evicted_on_the_next_push() {
+        let mut h = history(1, None, None);
+        h.push(entry(1, CopySource::User));
+        assert!(h.set_pinned(0, true));
+        h.push(entry(2, CopySource::User));
+        assert_eq!(tags(&h), [2, 1]);
+        assert!(h.set_pinned(1, false));
+        assert!(!h.set_pinned(2, true));
+        h.push(entry(3, CopySource::User));
+        assert_eq!(tags(&h), [3]);
+    }
+
+    #[test]
+    fn preview_neutralizes_controls_bidi_and_separators() {
+        let text = "a\tb\nc\x1b[31m\u{202e}d\u{2066}e\u{2028}f\u{2029}g";
+        assert_eq!(preview(text.as_bytes(), None, 80), "a b⏎c�[31m�d�e�f�g");
+        assert_eq!(preview(b"abcdef", None, 3), "abc…");
+    }
+}
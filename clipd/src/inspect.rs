@@ -0,0 +1,165 @@
+//! `Request::Info`: what an entry looks like, for checking the clipboard
+//! before pasting it. Only this summary leaves the daemon.
+
+use sha2::{Digest, Sha256};
+
+/// Magic numbers of the image formats worth naming.
+const IMAGES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"BM", "image/bmp"),
+    (b"II*\0", "image/tiff"),
+    (b"MM\0*", "image/tiff"),
+    (b"\0\0\x01\0", "image/x-icon"),
+];
+
+/// What `data` is: an image type from its magic number, `url` for a lone
+/// URL, `text` for other UTF-8, else `binary`.
+pub fn content(data: &[u8]) -> String {
+    if let Some((_, mime)) = IMAGES.iter().find(|(magic, _)| data.starts_with(magic)) {
+        return (*mime).to_owned();
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return "image/webp".to_owned();
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) if is_svg(text) => "image/svg+xml",
+        Ok(text) if is_url(text.trim()) => "url",
+        Ok(_) => "text",
+        Err(_) => "binary",
+    }
+    .to_owned()
+}
+
+/// A file extension for `mime`, a stored content type or what `content`
+/// returned, e.g. for `clipto paste --as-file`.
+pub fn extension(mime: &str) -> &'static str {
+    let base = mime.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    match base.as_str() {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/bmp" => "bmp",
+        "image/tiff" => "tiff",
+        "image/x-icon" | "image/vnd.microsoft.icon" => "ico",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        "text/html" => "html",
+        "application/json" => "json",
+        "application/pdf" => "pdf",
+        "text" | "url" => "txt",
+        m if m.starts_with("text/") => "txt",
+        _ => "bin",
+    }
+}
+
+/// Lines in `data` if it is text, counting an unterminated last line.
+pub fn lines(data: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(data).ok()?;
+    let newlines = text.bytes().filter(|&b| b == b'\n').count() as u64;
+    Some(newlines + u64::from(!text.is_empty() && !text.ends_with('\n')))
+}
+
+/// Lowercase hex SHA-256 of `data`.
+pub fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn is_svg(text: &str) -> bool {
+    let text = text.trim_start();
+    text.starts_with("<svg") || (text.starts_with("<?xml") && text.contains("<svg"))
+}
+
+/// A single `scheme://rest` token, e.g. `https://example.com/a`.
+fn is_url(text: &str) -> bool {
+    let Some((scheme, rest)) = text.split_once("://") else {
+        return false;
+    };
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        && !rest.is_empty()
+        && !text.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_and_urls() {
+        assert_eq!(content("héllo\nworld".as_bytes()), "text");
+        assert_eq!(content(b""), "text");
+        assert_eq!(content(b"  https://example.com/a?b=c\n"), "url");
+        assert_eq!(content(b"git+ssh://host/repo"), "url");
+        assert_eq!(content(b"see https://example.com"), "text");
+        assert_eq!(content(b"https://"), "text");
+        assert_eq!(content(b"1http://x"), "text");
+    }
+
+    #[test]
+    fn images_by_magic() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "image/png"),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", "image/jpeg"),
+            (b"GIF87a\x01\0", "image/gif"),
+            (b"GIF89a\x01\0", "image/gif"),
+            (b"BM\x1e\0\0\0", "image/bmp"),
+            (b"II*\0\x08\0\0\0", "image/tiff"),
+            (b"MM\0*\0\0\0\x08", "image/tiff"),
+            (b"\0\0\x01\0\x01\0", "image/x-icon"),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", "image/webp"),
+            (b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>", "image/svg+xml"),
+            (b"\n<?xml version=\"1.0\"?>\n<svg/>", "image/svg+xml"),
+        ];
+        for (data, mime) in cases {
+            assert_eq!(content(data), *mime, "{data:?}");
+        }
+        // A RIFF that isn't WebP, and one too short to say.
+        assert_eq!(content(b"RIFF\x24\0\0\0WAVEfmt "), "text");
+        assert_eq!(content(b"RIFF\x24\0\0\0WEB"), "text");
+        assert_eq!(content(b"<?xml version=\"1.0\"?><html/>"), "text");
+    }
+
+    #[test]
+    fn binary() {
+        assert_eq!(content(b"\xff\xfe\0\x01"), "binary");
+        assert_eq!(content(b"RIFF\x24\0\0\0WAVE\xff"), "binary");
+    }
+
+    #[test]
+    fn line_counts() {
+        assert_eq!(lines(b""), Some(0));
+        assert_eq!(lines(b"one"), Some(1));
+        assert_eq!(lines(b"one\n"), Some(1));
+        assert_eq!(lines(b"one\ntwo"), Some(2));
+        assert_eq!(lines(b"\n\n"), Some(2));
+        assert_eq!(lines(b"\xff\n"), None);
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(extension("image/png"), "png");
+        assert_eq!(extension("image/jpeg"), "jpg");
+        assert_eq!(extension("image/svg+xml"), "svg");
+        assert_eq!(extension("text/plain;charset=utf-8"), "txt");
+        assert_eq!(extension("Text/HTML; charset=utf-8"), "html");
+        assert_eq!(extension("url"), "txt");
+        assert_eq!(extension("binary"), "bin");
+        assert_eq!(extension("application/zip"), "bin");
+    }
+}
@@ -0,0 +1,137 @@
+//! `org.kde.klipper.klipper` at `/klipper`, so KDE widgets, KRunner, and
+//! scripts written against Klipper can use clipd as a drop-in backend.
+//!
+//! Klipper speaks strings, so binary entries are converted lossily. clipd has
+//! no separate selection buffer, so `clearClipboardContents` and
+//! `clearClipboardHistory` both empty everything. Calls are rate-limited as
+//! on `dev.clipto.Daemon`.
+
+use std::sync::Arc;
+
+use zbus::message::Header;
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, interface, Connection};
+use zeroize::Zeroize;
+
+use clipto_ipc::{CopySource, Request, Response};
+
+use crate::dbus::{into_result, throttle};
+use crate::{answer, wipe, Daemon};
+
+pub const NAME: &str = "org.kde.klipper";
+pub const PATH: &str = "/klipper";
+pub const INTERFACE: &str = "org.kde.klipper.klipper";
+
+pub struct Klipper {
+    pub daemon: Arc<Daemon>,
+}
+
+impl Klipper {
+    /// Through `dispatch`, so reading a paste-once entry destroys it. Klipper
+    /// has no error for a missing entry, just an empty string.
+    fn entry(&self, index: u32, throttled: Option<Response>) -> fdo::Result<String> {
+        match into_result(answer(&self.daemon, Request::Entry { index }, "klipper", throttled)) {
+            Ok(Response::Payload { mut data }) => {
+                let text = String::from_utf8_lossy(&data).into_owned();
+                data.zeroize();
+                Ok(text)
+            }
+            Err(e @ fdo::Error::LimitsExceeded(_)) => Err(e),
+            _ => Ok(String::new()),
+        }
+    }
+
+    fn clear(&self, throttled: Option<Response>) -> fdo::Result<()> {
+        into_result(answer(&self.daemon, Request::Clear, "klipper", throttled))?;
+        Ok(())
+    }
+}
+
+#[interface(name = "org.kde.klipper.klipper")]
+impl Klipper {
+    #[zbus(name = "getClipboardContents")]
+    async fn get_clipboard_contents(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<String> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        self.entry(0, throttled)
+    }
+
+    #[zbus(name = "setClipboardContents")]
+    async fn set_clipboard_contents(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        contents: String,
+    ) -> fdo::Result<()> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        let request = Request::Copy {
+            payload: contents.into_bytes(),
+            source: CopySource::User,
+            mime: None,
+            once: false,
+        };
+        into_result(answer(&self.daemon, request, "klipper", throttled))?;
+        Ok(())
+    }
+
+    #[zbus(name = "clearClipboardContents")]
+    async fn clear_clipboard_contents(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        self.clear(throttled)
+    }
+
+    #[zbus(name = "clearClipboardHistory")]
+    async fn clear_clipboard_history(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<()> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        self.clear(throttled)
+    }
+
+    /// Every history entry as text, newest first, except paste-once ones.
+    #[zbus(name = "getClipboardHistoryMenu")]
+    async fn get_clipboard_history_menu(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> fdo::Result<Vec<String>> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        let mut response =
+            into_result(answer(&self.daemon, Request::Export, "klipper", throttled))?;
+        let menu = match &response {
+            Response::Export(entries) => Ok(entries
+                .iter()
+                .map(|e| String::from_utf8_lossy(&e.data).into_owned())
+                .collect()),
+            _ => Err(fdo::Error::Failed("unexpected response to Export".into())),
+        };
+        wipe(&mut response);
+        menu
+    }
+
+    #[zbus(name = "getClipboardHistoryItem")]
+    async fn get_clipboard_history_item(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        index: i32,
+    ) -> fdo::Result<String> {
+        let throttled = throttle(&self.daemon, conn, &header).await?;
+        match u32::try_from(index) {
+            Ok(index) => self.entry(index, throttled),
+            Err(_) => Ok(String::new()),
+        }
+    }
+
+    #[zbus(signal, name = "clipboardHistoryUpdated")]
+    async fn clipboard_history_updated(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
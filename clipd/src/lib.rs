@@ -0,0 +1,1454 @@
+//! clipd, the clipboard daemon, as a library: the `clipto` binary runs it as
+//! `clipto daemon`.
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::io::ErrorKind;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit},
+    ChaCha20Poly1305, Nonce,
+};
+use rand::rngs::OsRng;
+use tracing::{debug, error, info, info_span, warn};
+use zeroize::{Zeroize, Zeroizing};
+
+use clipto_ipc::{
+    CopySource, Endpoint, EntryInfo, ExportedEntry, HistoryEntry, Request, Response, SearchMode,
+};
+
+mod backend;
+mod compositor;
+mod config;
+mod dbus;
+mod errorlog;
+mod events;
+mod files;
+mod history;
+mod inspect;
+mod klipper;
+mod line;
+mod lock;
+mod logfile;
+mod logging;
+#[cfg(target_os = "macos")]
+mod macos;
+mod notify;
+#[cfg(target_os = "linux")]
+mod portal;
+mod ratelimit;
+mod registers;
+#[cfg(target_os = "linux")]
+mod sandbox;
+mod stats;
+mod supervise;
+#[cfg(target_os = "linux")]
+mod wayland;
+#[cfg(target_os = "linux")]
+mod wsl;
+#[cfg(target_os = "linux")]
+mod x11;
+
+use backend::Backend;
+use config::Config;
+use errorlog::ErrorLog;
+use events::Changes;
+use files::PasteFiles;
+use history::{EncryptedBuffer, History};
+use notify::Notifier;
+use ratelimit::RateLimiter;
+use registers::Registers;
+use stats::Metrics;
+
+// ─── daemon state ─────────────────────────────────────────────────────────────
+
+struct State {
+    cipher: ChaCha20Poly1305,
+    history: History,
+    registers: Registers,
+    /// The last copy into each namespace, kept apart from the history.
+    namespaces: HashMap<String, EncryptedBuffer>,
+}
+
+impl State {
+    fn seal(&self, plaintext: &[u8], source: CopySource, client: &str) -> Result<EncryptedBuffer> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+        Ok(EncryptedBuffer {
+            nonce: nonce.into(),
+            ciphertext,
+            source,
+            mime: None,
+            stored: SystemTime::now(),
+            client: client.to_owned(),
+            pinned: false,
+            once: false,
+        })
+    }
+
+    fn open(&self, buf: &EncryptedBuffer) -> Result<Zeroizing<Vec<u8>>> {
+        let nonce = Nonce::from_slice(&buf.nonce);
+        let plaintext = self
+            .cipher
+            .decrypt(nonce, buf.ciphertext.as_slice())
+            .map_err(|_| anyhow::anyhow!("decryption failed"))?;
+        Ok(Zeroizing::new(plaintext))
+    }
+
+    /// Push a new current entry, returning its nonce, which identifies it.
+    fn store(
+        &mut self,
+        plaintext: &[u8],
+        source: CopySource,
+        client: &str,
+        mime: Option<String>,
+        once: bool,
+    ) -> Result<[u8; 12]> {
+        let mut buf = self.seal(plaintext, source, client)?;
+        buf.mime = mime;
+        buf.once = once;
+        let nonce = buf.nonce;
+        self.history.push(buf);
+        Ok(nonce)
+    }
+
+    /// Drop the history, every session's registers and every namespace.
+    fn clear(&mut self) {
+        self.history.clear();
+        self.registers.clear();
+        self.namespaces.clear();
+    }
+
+    fn load(&self) -> Result<Zeroizing<Vec<u8>>> {
+        self.load_at(0)
+    }
+
+    /// Decrypt history entry `index` (0 is the current clipboard).
+    fn load_at(&self, index: usize) -> Result<Zeroizing<Vec<u8>>> {
+        let buf = match self.history.get(index) {
+            Some(buf) => buf,
+            None if index == 0 => bail!("clipboard is empty"),
+            None => bail!("no history entry at index {index}"),
+        };
+        self.open(buf)
+    }
+
+    /// The nonce of entry `index` if it is paste-once.
+    fn once_at(&self, index: usize) -> Option<[u8; 12]> {
+        self.history.get(index).filter(|e| e.once).map(|e| e.nonce)
+    }
+
+    /// Remove entry `index` if it is paste-once, returning its nonce. Called
+    /// under the lock its content was read under, so no other read gets it.
+    fn take_once(&mut self, index: usize) -> Option<[u8; 12]> {
+        let nonce = self.once_at(index)?;
+        self.history.remove(&nonce);
+        Some(nonce)
+    }
+}
+
+/// Everything a connection handler needs, shared across threads.
+struct Daemon {
+    state: Mutex<State>,
+    metrics: Arc<Metrics>,
+    notifier: Notifier,
+    changes: Changes,
+    files: Arc<PasteFiles>,
+    errors: Arc<ErrorLog>,
+    echo: EchoFilter,
+    limiter: RateLimiter,
+    /// The desktop clipboard entries are mirrored to and from.
+    backend: Box<dyn Backend>,
+    /// Nonce of the paste-once entry the backend is offering, if any.
+    offered_once: Mutex<Option<[u8; 12]>>,
+    /// Longest request accepted, in bytes, on either socket.
+    max_request: u32,
+    /// How long one read or write on a client connection may block.
+    io_timeout: Duration,
+    /// Answer refused and empty reads alike (see `answer`).
+    uniform_denials: bool,
+}
+
+// ─── key loading ─────────────────────────────────────────────────────────────
+
+fn load_key() -> Result<Zeroizing<Vec<u8>>> {
+    #[cfg(target_os = "macos")]
+    if let Some(key) = keychain_key()? {
+        return Ok(key);
+    }
+
+    if let Ok(creds) = std::env::var("CREDENTIALS_DIRECTORY") {
+        let path = PathBuf::from(&creds).join("clipto-key");
+        if path.exists() {
+            let key = std::fs::read(&path)
+                .with_context(|| format!("failed to read key from {}", path.display()))?;
+            return Ok(Zeroizing::new(key));
+        }
+    }
+
+    if let Ok(key_file) = std::env::var("CLIPTO_KEY_FILE") {
+        let key = std::fs::read(&key_file)
+            .with_context(|| format!("failed to read key from {key_file}"))?;
+        return Ok(Zeroizing::new(key));
+    }
+
+    #[cfg(target_os = "macos")]
+    bail!(
+        "no key found: add a `clipto-key` generic password to the login keychain, \
+         or set CLIPTO_KEY_FILE for development"
+    );
+    #[cfg(not(target_os = "macos"))]
+    bail!(
+        "no key found: run as a systemd service with LoadCredentialEncrypted=clipto-key:…, \
+         or set CLIPTO_KEY_FILE for development"
+    )
+}
+
+/// The key stored hex-encoded as the `clipto-key` generic password in the
+/// login keychain. `None` if there is no such item.
+#[cfg(target_os = "macos")]
+fn keychain_key() -> Result<Option<Zeroizing<Vec<u8>>>> {
+    let out = std::process::Command::new("security")
+        .args(["find-generic-password", "-s", "clipto-key", "-w"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .context("failed to run security")?;
+    let hex = Zeroizing::new(out.stdout);
+    if !out.status.success() {
+        return Ok(None);
+    }
+    let hex = std::str::from_utf8(&hex).context("keychain item is not hex")?.trim();
+    if hex.len() % 2 != 0 {
+        bail!("keychain item is not hex");
+    }
+    let key = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .context("keychain item is not hex")?;
+    Ok(Some(Zeroizing::new(key)))
+}
+
+// ─── connection handler ───────────────────────────────────────────────────────
+
+fn handle_connection(mut stream: UnixStream, daemon: Arc<Daemon>) {
+    let client = peer_name(&stream);
+    let result = (|| -> Result<()> {
+        set_timeouts(&stream, daemon.io_timeout)?;
+        let request: Request = match clipto_ipc::read_frame_max(&mut stream, daemon.max_request) {
+            Ok(request) => request,
+            Err(e) => {
+                let Some(message) = rejection(&e) else { return Err(e) };
+                let response = Response::Error { message: message.clone() };
+                let _ = clipto_ipc::write_frame(&mut stream, &response);
+                bail!("{message}");
+            }
+        };
+        let throttled = throttle(&daemon, &stream);
+        if throttled.is_none() && matches!(request, Request::Subscribe) {
+            return subscribe(&daemon, &mut stream);
+        }
+        let mut response = answer(&daemon, request, &client, throttled);
+        let written = clipto_ipc::write_frame(&mut stream, &response);
+        wipe(&mut response);
+        written
+    })();
+
+    if let Err(e) = result {
+        Metrics::incr(&daemon.metrics.errors);
+        warn!("connection error: {e:#}");
+    }
+}
+
+/// Bound each read and write on `stream`, so a client that stalls mid-frame
+/// (or never sends one) gives up its thread instead of holding it forever.
+fn set_timeouts(stream: &UnixStream, timeout: Duration) -> Result<()> {
+    stream.set_read_timeout(Some(timeout)).context("failed to set read timeout")?;
+    stream.set_write_timeout(Some(timeout)).context("failed to set write timeout")
+}
+
+/// What to tell a client whose request couldn't be read, or `None` when it
+/// is gone and there's no one to tell.
+fn rejection(err: &anyhow::Error) -> Option<String> {
+    match err.downcast_ref::<std::io::Error>() {
+        Some(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+            Some("timed out waiting for the request".to_owned())
+        }
+        Some(_) => None,
+        None => Some(format!("bad request: {err:#}")),
+    }
+}
+
+/// How often an idle subscription checks that its client is still there.
+const HANGUP_CHECK: Duration = Duration::from_secs(30);
+
+/// Serve a `Request::Subscribe` connection: one `Response::Changed` per
+/// change, until the client hangs up.
+fn subscribe(daemon: &Daemon, stream: &mut UnixStream) -> Result<()> {
+    let changes = daemon.changes.subscribe();
+    clipto_ipc::write_frame(stream, &Response::Ok)?;
+    debug!("subscribed");
+    loop {
+        match changes.recv_timeout(HANGUP_CHECK) {
+            Ok(()) => {
+                if clipto_ipc::write_frame(stream, &Response::Changed).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if !hung_up(stream) => {}
+            Err(_) => break,
+        }
+    }
+    debug!("unsubscribed");
+    Ok(())
+}
+
+/// Whether the peer closed `stream`, without waiting for it to.
+fn hung_up(stream: &UnixStream) -> bool {
+    let mut buf = [0u8; 1];
+    // SAFETY: `buf` is valid for writes of its length.
+    let n = unsafe {
+        libc::recv(
+            stream.as_raw_fd(),
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            libc::MSG_PEEK | libc::MSG_DONTWAIT,
+        )
+    };
+    n == 0
+}
+
+/// `Response::Throttled` if the peer is over its rate limit, taking one of its
+/// tokens otherwise.
+fn throttle(daemon: &Daemon, stream: &UnixStream) -> Option<Response> {
+    let (pid, uid) = peer_ids(stream)?;
+    throttle_peer(daemon, pid, uid)
+}
+
+/// `throttle` for the process `pid` of user `uid`, however it reached clipd.
+fn throttle_peer(daemon: &Daemon, pid: libc::pid_t, uid: libc::uid_t) -> Option<Response> {
+    let client = daemon.limiter.client_of(pid);
+    let wait = daemon.limiter.check(uid, client).err()?;
+    Metrics::incr(&daemon.metrics.throttled);
+    debug!(uid, client, "throttled for {wait:?}");
+    Some(Response::Throttled { retry_after_ms: wait.as_millis() as u64 })
+}
+
+// ─── uniform denials ──────────────────────────────────────────────────────────
+
+/// The one refusal reads get under `uniform_denials`, whatever the reason.
+const DENIED: &str = "nothing to read";
+
+/// How long after it arrives a refused or empty read is answered, under
+/// `uniform_denials`. Far longer than working out any refusal takes, so none
+/// is answered early.
+const DENIAL_DELAY: Duration = Duration::from_millis(50);
+
+/// Answer `request`, unless `throttled` (the peer's rate-limit verdict)
+/// refused it. Under `uniform_denials`, a read that is refused or finds
+/// nothing gets the same answer (`denial`) no sooner than `DENIAL_DELAY`
+/// after it arrived: neither its size nor its timing tells a prober whether
+/// there was content it wasn't allowed.
+fn answer(
+    daemon: &Arc<Daemon>,
+    request: Request,
+    client: &str,
+    throttled: Option<Response>,
+) -> Response {
+    let arrived = Instant::now();
+    let denial = if daemon.uniform_denials { denial(&request) } else { None };
+    let response = throttled.unwrap_or_else(|| dispatch(daemon, request, client));
+    match denial {
+        Some(denial) if is_denial(&response) => {
+            std::thread::sleep(DENIAL_DELAY.saturating_sub(arrived.elapsed()));
+            denial
+        }
+        _ => response,
+    }
+}
+
+/// What a refused or empty `request` is answered with under
+/// `uniform_denials`, or `None` if its answer says nothing about what is
+/// stored.
+fn denial(request: &Request) -> Option<Response> {
+    match request {
+        Request::Paste
+        | Request::Entry { .. }
+        | Request::Info { .. }
+        | Request::PasteFile { .. }
+        | Request::GetRegister { .. }
+        | Request::GetNamespace { .. } => Some(Response::Error { message: DENIED.to_owned() }),
+        Request::History | Request::Search { .. } => Some(Response::History(Vec::new())),
+        Request::Export => Some(Response::Export(Vec::new())),
+        _ => None,
+    }
+}
+
+/// Whether `response` refuses a read or has nothing in it.
+fn is_denial(response: &Response) -> bool {
+    match response {
+        Response::Error { .. } | Response::Throttled { .. } => true,
+        Response::History(entries) => entries.is_empty(),
+        Response::Export(entries) => entries.is_empty(),
+        _ => false,
+    }
+}
+
+/// Credentials of the process at the other end of `stream`, as of `connect()`.
+#[cfg(target_os = "linux")]
+fn peer_cred(stream: &UnixStream) -> Option<libc::ucred> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes and `len` is its size.
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    (rc == 0).then_some(cred)
+}
+
+/// PID and UID of the process at the other end of `stream`.
+#[cfg(target_os = "linux")]
+fn peer_ids(stream: &UnixStream) -> Option<(libc::pid_t, libc::uid_t)> {
+    peer_cred(stream).filter(|c| c.pid > 0).map(|c| (c.pid, c.uid))
+}
+
+/// `comm[pid]` of the process at the other end of `stream`, for history
+/// metadata.
+#[cfg(target_os = "linux")]
+fn peer_name(stream: &UnixStream) -> String {
+    let Some(cred) = peer_cred(stream).filter(|c| c.pid > 0) else {
+        return "unknown".to_owned();
+    };
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", cred.pid)).unwrap_or_default();
+    format!("{}[{}]", comm.trim(), cred.pid)
+}
+
+/// Whether the peer runs as our user. Abstract sockets have no file mode to
+/// keep other users out, so connections to one are checked with this.
+#[cfg(target_os = "linux")]
+fn same_user(stream: &UnixStream) -> bool {
+    // SAFETY: getuid has no preconditions.
+    peer_cred(stream).is_some_and(|c| c.uid == unsafe { libc::getuid() })
+}
+
+/// Whether the peer runs as our user.
+#[cfg(not(target_os = "linux"))]
+fn same_user(stream: &UnixStream) -> bool {
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: `uid` and `gid` are valid for writes.
+    let rc = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    // SAFETY: getuid has no preconditions.
+    rc == 0 && uid == unsafe { libc::getuid() }
+}
+
+/// PID of the process at the other end of `stream`, as of `connect()`.
+#[cfg(target_os = "macos")]
+fn peer_pid(stream: &UnixStream) -> Option<libc::pid_t> {
+    let mut pid: libc::pid_t = 0;
+    let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+    // SAFETY: `pid` and `len` are valid for writes and `len` is its size.
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_LOCAL,
+            libc::LOCAL_PEERPID,
+            (&mut pid as *mut libc::pid_t).cast(),
+            &mut len,
+        )
+    };
+    (rc == 0 && pid > 0).then_some(pid)
+}
+
+/// PID and UID of the process at the other end of `stream`.
+#[cfg(target_os = "macos")]
+fn peer_ids(stream: &UnixStream) -> Option<(libc::pid_t, libc::uid_t)> {
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: `uid` and `gid` are valid for writes.
+    let rc = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    (rc == 0).then_some((peer_pid(stream)?, uid))
+}
+
+/// `comm[pid]` of the process at the other end of `stream`, for history
+/// metadata.
+#[cfg(target_os = "macos")]
+fn peer_name(stream: &UnixStream) -> String {
+    let Some(pid) = peer_pid(stream) else {
+        return "unknown".to_owned();
+    };
+    let mut name = [0u8; 64];
+    // SAFETY: `name` is valid for writes of its length.
+    let n = unsafe { libc::proc_name(pid, name.as_mut_ptr().cast(), name.len() as u32) };
+    let comm = String::from_utf8_lossy(&name[..n.max(0) as usize]);
+    format!("{comm}[{pid}]")
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Longest history preview sent to clients, in characters.
+const PREVIEW_CHARS: usize = 200;
+
+/// Execute one request against the daemon. Shared by every front end (the
+/// framed socket, the line socket, D-Bus). `client` names the requester in
+/// the metadata of entries it copies.
+fn dispatch(daemon: &Arc<Daemon>, request: Request, client: &str) -> Response {
+    let metrics = &daemon.metrics;
+
+    match request {
+        Request::Copy { payload, source, mime, once } => {
+            let payload = Zeroizing::new(payload);
+            debug!(bytes = payload.len(), ?source, client, ?mime, once, "copy");
+            if source == CopySource::Wayland {
+                if daemon.echo.is_echo(&payload) {
+                    debug!("dropping echo of our own desktop clipboard sync");
+                    return Response::Ok;
+                }
+                daemon.echo.forget();
+            }
+            let mut st = daemon.state.lock().unwrap();
+            let client = if source == CopySource::Wayland { daemon.backend.name() } else { client };
+            match st.store(&payload, source, client, mime.clone(), once) {
+                Ok(nonce) => {
+                    let should_sync = source == CopySource::User;
+                    drop(st);
+
+                    Metrics::incr(&metrics.copies);
+                    metrics.bytes_stored.store(payload.len() as u64, Ordering::Relaxed);
+                    daemon.changes.publish();
+                    // A paste-once entry is a secret; don't announce it.
+                    if !once {
+                        daemon.notifier.copied(
+                            payload.len(),
+                            source == CopySource::Wayland,
+                            mime.as_deref().unwrap_or_else(|| notify::sniff_mime(&payload)),
+                        );
+                    }
+
+                    if should_sync && once {
+                        offer_once(daemon, &payload, mime.as_deref(), nonce);
+                    } else if should_sync {
+                        sync(daemon, &payload, mime.as_deref());
+                    }
+
+                    Response::Ok
+                }
+                Err(e) => {
+                    Metrics::incr(&metrics.rejected_payloads);
+                    Metrics::incr(&metrics.errors);
+                    error!("store failed: {e:#}");
+                    Response::Error { message: e.to_string() }
+                }
+            }
+        }
+
+        Request::Paste => {
+            debug!("paste");
+            let mut st = daemon.state.lock().unwrap();
+            match st.load() {
+                Ok(data) => {
+                    let once = st.take_once(0);
+                    drop(st);
+                    if let Some(nonce) = once {
+                        once_destroyed(daemon, nonce, true);
+                    }
+                    Metrics::incr(&metrics.pastes);
+                    Response::Payload { data: take_plaintext(data) }
+                }
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::Entry { index } => {
+            debug!(index, "entry");
+            let mut st = daemon.state.lock().unwrap();
+            match st.load_at(index as usize) {
+                Ok(data) => {
+                    let once = st.take_once(index as usize);
+                    drop(st);
+                    if let Some(nonce) = once {
+                        once_destroyed(daemon, nonce, true);
+                    }
+                    Metrics::incr(&metrics.pastes);
+                    Response::Payload { data: take_plaintext(data) }
+                }
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::SetRegister { session, name, payload } => {
+            let payload = Zeroizing::new(payload);
+            debug!(session = %session.id, register = %name, bytes = payload.len(), "set register");
+            let mut st = daemon.state.lock().unwrap();
+            let result = st
+                .seal(&payload, CopySource::User, client)
+                .and_then(|buf| st.registers.set(&session, &name, buf));
+            match result {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::GetRegister { session, name } => {
+            debug!(%session, register = %name, "get register");
+            let st = daemon.state.lock().unwrap();
+            let result = match st.registers.get(&session, &name) {
+                Some(buf) => st.open(buf),
+                None => Err(anyhow::anyhow!("register {name:?} is empty in session {session:?}")),
+            };
+            match result {
+                Ok(data) => {
+                    Metrics::incr(&metrics.pastes);
+                    Response::Payload { data: take_plaintext(data) }
+                }
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::SetNamespace { namespace, payload, mime } => {
+            let payload = Zeroizing::new(payload);
+            debug!(%namespace, bytes = payload.len(), client, ?mime, "set namespace");
+            let mut st = daemon.state.lock().unwrap();
+            match st.seal(&payload, CopySource::User, client) {
+                Ok(mut buf) => {
+                    buf.mime = mime;
+                    st.namespaces.insert(namespace, buf);
+                    drop(st);
+
+                    Metrics::incr(&metrics.copies);
+                    metrics.bytes_stored.store(payload.len() as u64, Ordering::Relaxed);
+                    daemon.changes.publish();
+                    Response::Ok
+                }
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::GetNamespace { namespace } => {
+            debug!(%namespace, "get namespace");
+            let st = daemon.state.lock().unwrap();
+            let Some(buf) = st.namespaces.get(&namespace) else {
+                drop(st);
+                return dispatch(daemon, Request::Paste, client);
+            };
+            match st.open(buf) {
+                Ok(data) => {
+                    Metrics::incr(&metrics.pastes);
+                    Response::Payload { data: take_plaintext(data) }
+                }
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::Export => {
+            debug!("export");
+            let st = daemon.state.lock().unwrap();
+            let entries: Result<Vec<ExportedEntry>> = st
+                .history
+                .iter()
+                .filter(|entry| !entry.once)
+                .map(|entry| {
+                    Ok(ExportedEntry {
+                        source: entry.source,
+                        pinned: entry.pinned,
+                        mime: entry.mime.clone(),
+                        stored_unix: unix_secs(entry.stored),
+                        client: entry.client.clone(),
+                        data: take_plaintext(st.open(entry)?),
+                    })
+                })
+                .collect();
+            match entries {
+                Ok(entries) => Response::Export(entries),
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::Import { mut entries } => {
+            debug!(entries = entries.len(), "import");
+            let mut st = daemon.state.lock().unwrap();
+            // Oldest first, so the archive's newest entry ends up on top.
+            for e in entries.iter_mut().rev() {
+                let sealed = st.seal(&e.data, e.source, &e.client);
+                e.data.zeroize();
+                let mut buf = match sealed {
+                    Ok(buf) => buf,
+                    Err(err) => {
+                        entries.iter_mut().for_each(|e| e.data.zeroize());
+                        return Response::Error { message: err.to_string() };
+                    }
+                };
+                buf.stored = UNIX_EPOCH + Duration::from_secs(e.stored_unix);
+                buf.pinned = e.pinned;
+                buf.mime = e.mime.clone();
+                st.history.push(buf);
+            }
+            let current = st.history.get(0).map_or(0, |e| e.plaintext_len());
+            drop(st);
+
+            metrics.bytes_stored.store(current as u64, Ordering::Relaxed);
+            daemon.changes.publish();
+            Response::Ok
+        }
+
+        Request::Info { index } => {
+            debug!(index, "info");
+            let st = daemon.state.lock().unwrap();
+            match entry_info(&st, index) {
+                Ok(info) => Response::Info(info),
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::Promote { index } => {
+            debug!(index, "promote");
+            let mut st = daemon.state.lock().unwrap();
+            if !st.history.promote(index as usize) {
+                return Response::Error { message: format!("no history entry at index {index}") };
+            }
+            let result = st.load();
+            let mime = st.history.get(0).and_then(|e| e.mime.clone());
+            let once = st.once_at(0);
+            drop(st);
+
+            match result {
+                Ok(data) => {
+                    metrics.bytes_stored.store(data.len() as u64, Ordering::Relaxed);
+                    daemon.changes.publish();
+                    match once {
+                        Some(nonce) => offer_once(daemon, &data, mime.as_deref(), nonce),
+                        None => sync(daemon, &data, mime.as_deref()),
+                    }
+                    Response::Ok
+                }
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::Pin { index, pinned } => {
+            debug!(index, pinned, "pin");
+            let mut st = daemon.state.lock().unwrap();
+            if st.history.set_pinned(index as usize, pinned) {
+                drop(st);
+                daemon.changes.publish();
+                Response::Ok
+            } else {
+                Response::Error { message: format!("no history entry at index {index}") }
+            }
+        }
+
+        Request::History => {
+            let st = daemon.state.lock().unwrap();
+            Response::History(list_history(&st, |_, _| true))
+        }
+
+        Request::Search { query, mode, ignore_case } => {
+            debug!(?mode, "search");
+            let pattern = match mode {
+                SearchMode::Substring => regex::escape(&query),
+                SearchMode::Regex => query,
+            };
+            let re = regex::bytes::RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .size_limit(1 << 20)
+                .build();
+            match re {
+                Ok(re) => {
+                    let st = daemon.state.lock().unwrap();
+                    let keep = |e: &EncryptedBuffer, data: &[u8]| !e.once && re.is_match(data);
+                    Response::History(list_history(&st, keep))
+                }
+                Err(e) => Response::Error { message: format!("invalid pattern: {e}") },
+            }
+        }
+
+        Request::PasteFile { index } => {
+            debug!(index, "paste to file");
+            let st = daemon.state.lock().unwrap();
+            // A file would outlive the one paste, until the collector got to it.
+            if st.once_at(index as usize).is_some() {
+                return Response::Error {
+                    message: "a paste-once entry can't be pasted to a file".to_owned(),
+                };
+            }
+            let mime = st.history.get(index as usize).and_then(|e| e.mime.clone());
+            let result = st.load_at(index as usize).and_then(|data| {
+                let mime = mime.unwrap_or_else(|| inspect::content(&data));
+                daemon.files.write(&data, inspect::extension(&mime))
+            });
+            drop(st);
+            match result {
+                Ok(path) => {
+                    Metrics::incr(&metrics.pastes);
+                    Response::File { path }
+                }
+                Err(e) => Response::Error { message: e.to_string() },
+            }
+        }
+
+        Request::Clear => {
+            debug!("clear");
+            clear(daemon, None);
+            Response::Ok
+        }
+
+        Request::Stats => Response::Stats(metrics.snapshot()),
+
+        Request::Errors => Response::Errors(daemon.errors.snapshot()),
+
+        Request::Status => Response::Status(daemon.backend.status()),
+
+        // Only the framed socket keeps a connection open for it.
+        Request::Subscribe => {
+            Response::Error { message: "subscribing needs the clipd socket".to_owned() }
+        }
+    }
+}
+
+/// Decrypt each history entry and list those `keep` accepts, with previews,
+/// pinned entries first. Plaintext never leaves this function except as a
+/// truncated preview, and not even that for paste-once entries.
+fn list_history(
+    st: &State,
+    keep: impl Fn(&EncryptedBuffer, &[u8]) -> bool,
+) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = (0..st.history.len())
+        .filter_map(|i| {
+            let entry = st.history.get(i)?;
+            let data = st.load_at(i).ok()?;
+            keep(entry, &data).then(|| HistoryEntry {
+                index: i as u32,
+                source: entry.source,
+                pinned: entry.pinned,
+                once: entry.once,
+                mime: entry.mime.clone(),
+                stored_unix: unix_secs(entry.stored),
+                client: entry.client.clone(),
+                size: data.len() as u64,
+                preview: if entry.once {
+                    format!("[paste once, {} bytes]", data.len())
+                } else {
+                    history::preview(&data, entry.mime.as_deref(), PREVIEW_CHARS)
+                },
+            })
+        })
+        .collect();
+    // Stable, so each group stays newest first.
+    entries.sort_by_key(|e| !e.pinned);
+    entries
+}
+
+/// Move decrypted `data` into a response without copying it. Whoever sends
+/// the response then `wipe`s it.
+fn take_plaintext(mut data: Zeroizing<Vec<u8>>) -> Vec<u8> {
+    std::mem::take(&mut *data)
+}
+
+/// Zeroize the plaintext a sent response carried.
+fn wipe(response: &mut Response) {
+    match response {
+        Response::Payload { data } => data.zeroize(),
+        Response::Export(entries) => entries.iter_mut().for_each(|e| e.data.zeroize()),
+        _ => {}
+    }
+}
+
+/// Describe history entry `index` for `Request::Info`.
+fn entry_info(st: &State, index: u32) -> Result<EntryInfo> {
+    let data = st.load_at(index as usize)?;
+    let entry = st.history.get(index as usize).expect("load_at found the entry");
+    Ok(EntryInfo {
+        index,
+        source: entry.source,
+        pinned: entry.pinned,
+        once: entry.once,
+        mime: entry.mime.clone(),
+        content: inspect::content(&data),
+        size: data.len() as u64,
+        lines: inspect::lines(&data),
+        sha256: (!entry.once).then(|| inspect::sha256(&data)),
+        stored_unix: unix_secs(entry.stored),
+        client: entry.client.clone(),
+    })
+}
+
+/// Push `payload` to the desktop clipboard, counting the outcome.
+fn sync(daemon: &Daemon, payload: &[u8], mime: Option<&str>) {
+    // Before the backend runs, so the watcher can't see the change first.
+    daemon.echo.sent(payload);
+    match daemon.backend.set(payload, mime) {
+        Ok(true) => Metrics::incr(&daemon.metrics.wayland_syncs),
+        Ok(false) => {}
+        Err(e) => {
+            Metrics::incr(&daemon.metrics.errors);
+            warn!("{} sync failed: {e:#}", daemon.backend.name());
+        }
+    }
+}
+
+/// Offer paste-once entry `nonce` to the desktop clipboard. It is destroyed
+/// once the backend lets go of it, pasted or replaced.
+fn offer_once(daemon: &Arc<Daemon>, payload: &[u8], mime: Option<&str>, nonce: [u8; 12]) {
+    daemon.echo.sent(payload);
+    *daemon.offered_once.lock().unwrap() = Some(nonce);
+    let weak = Arc::downgrade(daemon);
+    let done = Box::new(move || {
+        if let Some(daemon) = weak.upgrade() {
+            destroy_once(&daemon, nonce);
+        }
+    });
+    let result = daemon.backend.set_once(payload, mime, done);
+    if !matches!(result, Ok(true)) {
+        let mut offered = daemon.offered_once.lock().unwrap();
+        if *offered == Some(nonce) {
+            *offered = None;
+        }
+    }
+    match result {
+        Ok(true) => Metrics::incr(&daemon.metrics.wayland_syncs),
+        Ok(false) => debug!("{} can't offer a single paste; kept out of it", daemon.backend.name()),
+        Err(e) => {
+            Metrics::incr(&daemon.metrics.errors);
+            warn!("{} sync failed: {e:#}", daemon.backend.name());
+        }
+    }
+}
+
+/// Destroy paste-once entry `nonce`, just pasted from the desktop clipboard.
+fn destroy_once(daemon: &Daemon, nonce: [u8; 12]) {
+    if !daemon.state.lock().unwrap().history.remove(&nonce) {
+        return; // already replaced, or pasted over the socket
+    }
+    once_destroyed(daemon, nonce, false);
+}
+
+/// Follow up on paste-once entry `nonce`, just removed from the history.
+/// After a paste over the socket (`withdraw`), the desktop clipboard stops
+/// offering it too.
+fn once_destroyed(daemon: &Daemon, nonce: [u8; 12], withdraw: bool) {
+    let offered = {
+        let mut offered = daemon.offered_once.lock().unwrap();
+        let was = *offered == Some(nonce);
+        if was {
+            *offered = None;
+        }
+        was
+    };
+    let current = {
+        let st = daemon.state.lock().unwrap();
+        st.history.get(0).map_or(0, |e| e.plaintext_len())
+    };
+
+    info!("paste-once entry destroyed");
+    daemon.metrics.bytes_stored.store(current as u64, Ordering::Relaxed);
+    daemon.changes.publish();
+    if withdraw && offered {
+        daemon.echo.sent(b"");
+        if let Err(e) = daemon.backend.clear() {
+            Metrics::incr(&daemon.metrics.errors);
+            warn!("{} clear failed: {e:#}", daemon.backend.name());
+        }
+    }
+}
+
+/// Drop the stored entry and empty the desktop clipboard. `reason` says why,
+/// for a wipe the user didn't ask for.
+fn clear(daemon: &Daemon, reason: Option<&'static str>) {
+    daemon.state.lock().unwrap().clear();
+    *daemon.offered_once.lock().unwrap() = None;
+    daemon.files.collect(true);
+    daemon.metrics.bytes_stored.store(0, Ordering::Relaxed);
+    daemon.changes.publish();
+
+    daemon.echo.sent(b"");
+    if let Err(e) = daemon.backend.clear() {
+        Metrics::incr(&daemon.metrics.errors);
+        warn!("{} clear failed: {e:#}", daemon.backend.name());
+    }
+    daemon.notifier.cleared(reason);
+}
+
+// ─── desktop clipboard sync ───────────────────────────────────────────────────
+
+/// Remembers a keyed hash of the last payload handed to the backend, so its
+/// echo through the watcher is recognized and not stored again.
+struct EchoFilter {
+    key: RandomState,
+    last: Mutex<Option<u64>>,
+}
+
+impl EchoFilter {
+    fn new() -> Self {
+        Self { key: RandomState::new(), last: Mutex::new(None) }
+    }
+
+    fn sent(&self, payload: &[u8]) {
+        *self.last.lock().unwrap() = Some(self.key.hash_one(payload));
+    }
+
+    fn is_echo(&self, payload: &[u8]) -> bool {
+        *self.last.lock().unwrap() == Some(self.key.hash_one(payload))
+    }
+
+    /// The desktop clipboard now holds something else; a later copy of the synced
+    /// payload is a real copy again.
+    fn forget(&self) {
+        *self.last.lock().unwrap() = None;
+    }
+}
+
+/// Leave the current entry in the desktop clipboard once clipd is gone.
+fn persist_entry(daemon: &Daemon) -> Result<()> {
+    let st = daemon.state.lock().unwrap();
+    if st.once_at(0).is_some() {
+        return Ok(()); // must not outlive its one paste
+    }
+    let data = match st.load() {
+        Ok(data) => data,
+        Err(_) => return Ok(()), // nothing to keep
+    };
+    let mime = st.history.get(0).and_then(|e| e.mime.clone());
+    drop(st);
+    daemon.backend.persist(&data, mime.as_deref())
+}
+
+/// Bind an owner-only Unix socket at `path`, replacing any stale one.
+fn bind_socket(path: &Path) -> Result<UnixListener> {
+    let _ = std::fs::remove_file(path);
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("failed to bind to {}", path.display()))?;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .context("failed to set socket permissions")?;
+
+    Ok(listener)
+}
+
+// ─── run ──────────────────────────────────────────────────────────────────────
+
+/// Run the daemon in this process until SIGINT or SIGTERM, which exit it.
+/// Called by `clipto daemon`; the same executable is what `wl-paste --watch`
+/// runs for each compositor clipboard change.
+pub fn run() -> Result<()> {
+    let config = Config::load()?;
+    let errors = logging::init(&config);
+
+    let key = load_key()?;
+
+    if key.len() != 32 {
+        bail!("key must be exactly 32 bytes, got {}", key.len());
+    }
+
+    let cipher = ChaCha20Poly1305::new_from_slice(&key)
+        .map_err(|_| anyhow::anyhow!("failed to create cipher from key"))?;
+    drop(key);
+
+    let clipto_bin = std::env::current_exe().context("failed to locate the clipto executable")?;
+    let backend = backend::detect(&config, clipto_bin);
+    #[cfg(target_os = "linux")]
+    if config.sandbox.enabled {
+        let mut programs = backend.programs();
+        programs.extend(compositor::PROGRAMS.iter().map(PathBuf::from));
+        sandbox::apply(&config, &programs).context("failed to apply the sandbox")?;
+    }
+
+    let daemon = Arc::new(Daemon {
+        state: Mutex::new(State {
+            cipher,
+            history: History::new(config.history.clone()),
+            registers: Registers::default(),
+            namespaces: HashMap::new(),
+        }),
+        metrics: Arc::new(Metrics::new()),
+        notifier: Notifier::start(config.notifications.clone()),
+        changes: Changes::default(),
+        files: Arc::new(PasteFiles::new(Duration::from_secs(
+            config.paste_file_ttl_secs.unwrap_or(300),
+        ))?),
+        errors,
+        echo: EchoFilter::new(),
+        limiter: RateLimiter::new(config.rate_limit.clone()),
+        backend,
+        offered_once: Mutex::new(None),
+        max_request: u32::try_from(config.max_request_bytes.unwrap_or(256 << 20))
+            .unwrap_or(u32::MAX),
+        io_timeout: Duration::from_secs(config.io_timeout_secs.unwrap_or(10).max(1)),
+        uniform_denials: config.uniform_denials,
+    });
+    daemon.files.collect(true);
+    daemon.files.start_collector();
+    registers::start_reaper(Arc::clone(&daemon));
+
+    if let Some(path) = config.stats_textfile.clone() {
+        let interval = Duration::from_secs(config.stats_interval_secs.unwrap_or(15).max(1));
+        stats::start_textfile_writer(Arc::clone(&daemon.metrics), path, interval);
+    }
+
+    let endpoint = clipto_ipc::socket()?;
+    let mut socket_paths = Vec::new();
+    let listener = match &endpoint {
+        Endpoint::Path(path) => {
+            socket_paths.push(path.clone());
+            bind_socket(path)?
+        }
+        Endpoint::Abstract(_) => {
+            endpoint.bind().with_context(|| format!("failed to bind to {endpoint}"))?
+        }
+    };
+    let check_peer = matches!(endpoint, Endpoint::Abstract(_));
+
+    if config.line_socket {
+        let line_path = clipto_ipc::line_socket_path()?;
+        line::start(bind_socket(&line_path)?, Arc::clone(&daemon));
+        info!("line protocol listening on {}", line_path.display());
+        socket_paths.push(line_path);
+    }
+
+    {
+        let daemon = Arc::clone(&daemon);
+        let persist = config.persist_on_exit;
+        ctrlc::set_handler(move || {
+            for path in &socket_paths {
+                let _ = std::fs::remove_file(path);
+            }
+            if persist {
+                if let Err(e) = persist_entry(&daemon) {
+                    warn!("clipboard not persisted: {e:#}");
+                }
+            }
+            info!("clipd shutting down");
+            std::process::exit(0);
+        })
+        .context("failed to set signal handler")?;
+    }
+
+    if config.dbus || config.klipper {
+        dbus::start(Arc::clone(&daemon), config.dbus, config.klipper);
+    }
+
+    if config.wipe_on_lock || config.wipe_on_sleep {
+        lock::start(Arc::clone(&daemon), config.wipe_on_lock, config.wipe_on_sleep);
+    }
+
+    // Always start the watcher — under Wayland it waits silently for the
+    // compositor to appear.
+    daemon.backend.start_watcher(Arc::clone(&daemon));
+
+    info!("clipd listening on {endpoint}");
+
+    let next_conn = AtomicU64::new(1);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) if check_peer && !same_user(&stream) => {
+                Metrics::incr(&daemon.metrics.errors);
+                warn!("refused a connection from another user");
+            }
+            Ok(stream) => {
+                let daemon = Arc::clone(&daemon);
+                let span = info_span!("conn", id = next_conn.fetch_add(1, Ordering::Relaxed));
+                std::thread::spawn(move || span.in_scope(|| handle_connection(stream, daemon)));
+            }
+            Err(e) => {
+                Metrics::incr(&daemon.metrics.errors);
+                error!("accept error: {e}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+
+    use clipto_ipc::Status;
+
+    use super::*;
+
+    type Done = Box<dyn FnOnce() + Send>;
+
+    /// A desktop clipboard that takes single-paste offers and records
+    /// clears.
+    #[derive(Default)]
+    struct FakeBackend {
+        /// `done` of the offer it holds.
+        offer: Arc<Mutex<Option<Done>>>,
+        clears: Arc<AtomicU64>,
+    }
+
+    impl Backend for FakeBackend {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn set(&self, _payload: &[u8], _mime: Option<&str>) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn set_once(
+            &self,
+            _payload: &[u8],
+            _mime: Option<&str>,
+            done: Done,
+        ) -> Result<bool> {
+            *self.offer.lock().unwrap() = Some(done);
+            Ok(true)
+        }
+
+        fn clear(&self) -> Result<()> {
+            self.clears.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn persist(&self, _payload: &[u8], _mime: Option<&str>) -> Result<()> {
+            Ok(())
+        }
+
+        fn start_watcher(&self, _daemon: Arc<Daemon>) {}
+
+        fn status(&self) -> Status {
+            Status {
+                backend: "fake".to_owned(),
+                display: None,
+                watcher: clipto_ipc::WatcherState::Stopped,
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        fn programs(&self) -> Vec<PathBuf> {
+            Vec::new()
+        }
+    }
+
+    fn daemon(backend: FakeBackend) -> Arc<Daemon> {
+        let config = Config::default();
+        // `PasteFiles` wants a runtime directory, though no test writes to it.
+        #[cfg(not(target_os = "macos"))]
+        if std::env::var_os("XDG_RUNTIME_DIR").is_none() {
+            std::env::set_var("XDG_RUNTIME_DIR", std::env::temp_dir());
+        }
+        Arc::new(Daemon {
+            state: Mutex::new(State {
+                cipher: ChaCha20Poly1305::new(&[7; 32].into()),
+                history: History::new(config.history.clone()),
+                registers: Registers::default(),
+                namespaces: HashMap::new(),
+            }),
+            metrics: Arc::new(Metrics::new()),
+            notifier: Notifier::start(config.notifications.clone()),
+            changes: Changes::default(),
+            files: Arc::new(PasteFiles::new(Duration::from_secs(300)).unwrap()),
+            errors: Arc::default(),
+            echo: EchoFilter::new(),
+            limiter: RateLimiter::new(config.rate_limit.clone()),
+            backend: Box::new(backend),
+            offered_once: Mutex::new(None),
+            max_request: 1024,
+            io_timeout: Duration::from_secs(1),
+            uniform_denials: false,
+        })
+    }
+
+    fn copy(daemon: &Arc<Daemon>, payload: &[u8], once: bool) {
+        let request =
+            Request::Copy { payload: payload.to_vec(), source: CopySource::User, mime: None, once };
+        assert!(matches!(dispatch(daemon, request, "test"), Response::Ok));
+    }
+
+    fn payload(response: Response) -> Vec<u8> {
+        match response {
+            Response::Payload { data } => data,
+            other => panic!("expected a payload, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn paste_once_is_destroyed_after_paste() {
+        let backend = FakeBackend::default();
+        let clears = Arc::clone(&backend.clears);
+        let daemon = daemon(backend);
+        copy(&daemon, b"older", false);
+        copy(&daemon, b"secret", true);
+
+        assert_eq!(payload(dispatch(&daemon, Request::Paste, "test")), b"secret");
+        // The desktop clipboard stops offering it, and the entry it replaced
+        // is the current clipboard again.
+        assert_eq!(clears.load(Ordering::Relaxed), 1);
+        assert_eq!(payload(dispatch(&daemon, Request::Paste, "test")), b"older");
+        assert_eq!(daemon.state.lock().unwrap().history.len(), 1);
+    }
+
+    #[test]
+    fn paste_once_read_by_index_is_destroyed() {
+        let daemon = daemon(FakeBackend::default());
+        copy(&daemon, b"secret", true);
+        let entry = dispatch(&daemon, Request::Entry { index: 0 }, "dbus");
+        assert_eq!(payload(entry), b"secret");
+        assert!(matches!(
+            dispatch(&daemon, Request::Paste, "test"),
+            Response::Error { message } if message == "clipboard is empty"
+        ));
+    }
+
+    #[test]
+    fn paste_once_is_not_pasted_to_a_file() {
+        let daemon = daemon(FakeBackend::default());
+        copy(&daemon, b"secret", true);
+        assert!(matches!(
+            dispatch(&daemon, Request::PasteFile { index: 0 }, "test"),
+            Response::Error { message } if message.contains("paste-once")
+        ));
+        assert_eq!(payload(dispatch(&daemon, Request::Paste, "test")), b"secret");
+    }
+
+    #[test]
+    fn uniform_denials_hide_why_a_read_failed() {
+        let mut daemon = daemon(FakeBackend::default());
+        Arc::get_mut(&mut daemon).unwrap().uniform_denials = true;
+        let throttled = || Some(Response::Throttled { retry_after_ms: 100 });
+        let message = |response: Response| match response {
+            Response::Error { message } => message,
+            other => panic!("expected an error, got {other:?}"),
+        };
+
+        let arrived = Instant::now();
+        let empty = message(answer(&daemon, Request::Paste, "test", None));
+        assert!(arrived.elapsed() >= DENIAL_DELAY);
+        assert_eq!(empty, DENIED);
+        assert_eq!(message(answer(&daemon, Request::Paste, "test", throttled())), DENIED);
+        let register = Request::GetRegister { session: "s".to_owned(), name: "a".to_owned() };
+        assert_eq!(message(answer(&daemon, register, "test", None)), DENIED);
+        assert!(matches!(
+            answer(&daemon, Request::History, "test", throttled()),
+            Response::History(entries) if entries.is_empty()
+        ));
+
+        // Content is still pasted, and writes still learn they were throttled.
+        copy(&daemon, b"data", false);
+        assert_eq!(payload(answer(&daemon, Request::Paste, "test", None)), b"data");
+        assert!(matches!(
+            answer(&daemon, Request::Clear, "test", throttled()),
+            Response::Throttled { .. }
+        ));
+    }
+
+    #[test]
+    fn namespace_copy_is_published_and_counted() {
+        let daemon = daemon(FakeBackend::default());
+        let changes = daemon.changes.subscribe();
+        let request = Request::SetNamespace {
+            namespace: "project".to_owned(),
+            payload: b"scoped".to_vec(),
+            mime: None,
+        };
+        assert!(matches!(dispatch(&daemon, request, "test"), Response::Ok));
+        assert!(changes.try_recv().is_ok());
+        assert_eq!(daemon.metrics.bytes_stored.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn paste_once_is_destroyed_after_desktop_paste() {
+        let backend = FakeBackend::default();
+        let (offer, clears) = (Arc::clone(&backend.offer), Arc::clone(&backend.clears));
+        let daemon = daemon(backend);
+        copy(&daemon, b"secret", true);
+
+        let done = offer.lock().unwrap().take().unwrap();
+        done();
+        assert_eq!(daemon.state.lock().unwrap().history.len(), 0);
+        // The desktop clipboard already let go of it; nothing to withdraw.
+        assert_eq!(clears.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn late_desktop_paste_leaves_newer_entries() {
+        let backend = FakeBackend::default();
+        let offer = Arc::clone(&backend.offer);
+        let daemon = daemon(backend);
+        copy(&daemon, b"secret", true);
+        let done = offer.lock().unwrap().take().unwrap();
+        copy(&daemon, b"newer", false);
+
+        done();
+        assert_eq!(daemon.state.lock().unwrap().history.len(), 1);
+        assert_eq!(payload(dispatch(&daemon, Request::Paste, "test")), b"newer");
+    }
+
+    #[test]
+    fn concurrent_pastes_get_a_paste_once_entry_once() {
+        let daemon = daemon(FakeBackend::default());
+        for _ in 0..200 {
+            copy(&daemon, b"secret", true);
+            let start = Arc::new(std::sync::Barrier::new(2));
+            let pastes: Vec<_> = (0..2)
+                .map(|_| {
+                    let (daemon, start) = (Arc::clone(&daemon), Arc::clone(&start));
+                    std::thread::spawn(move || {
+                        start.wait();
+                        dispatch(&daemon, Request::Paste, "test")
+                    })
+                })
+                .collect();
+            let payloads = pastes
+                .into_iter()
+                .map(|p| p.join().unwrap())
+                .filter(|r| matches!(r, Response::Payload { .. }))
+                .count();
+            assert_eq!(payloads, 1);
+        }
+    }
+
+    fn read_error(buf: Vec<u8>) -> anyhow::Error {
+        clipto_ipc::read_frame_max::<Request>(&mut Cursor::new(buf), 1024).unwrap_err()
+    }
+
+    #[test]
+    fn oversize_request_is_rejected() {
+        let message = rejection(&read_error(u32::MAX.to_le_bytes().to_vec())).unwrap();
+        assert_eq!(message, "bad request: frame of 4294967295 bytes is over the 1024-byte limit");
+    }
+
+    #[test]
+    fn malformed_request_is_rejected() {
+        let mut buf = 4u32.to_le_bytes().to_vec();
+        buf.extend_from_slice(&u32::MAX.to_le_bytes());
+        let message = rejection(&read_error(buf)).unwrap();
+        assert!(message.starts_with("bad request: deserialization failed"), "{message}");
+    }
+
+    #[test]
+    fn timeout_is_rejected() {
+        for kind in [ErrorKind::TimedOut, ErrorKind::WouldBlock] {
+            let err = anyhow::Error::from(io::Error::from(kind));
+            assert_eq!(rejection(&err).unwrap(), "timed out waiting for the request");
+        }
+    }
+
+    #[test]
+    fn hangup_is_not_answered() {
+        assert_eq!(rejection(&read_error(vec![1, 0])), None);
+        let mut truncated = 8u32.to_le_bytes().to_vec();
+        truncated.push(0);
+        assert_eq!(rejection(&read_error(truncated)), None);
+    }
+}
@@ -0,0 +1,144 @@
+//! Plain-text front end for editors that can open a Unix socket but not speak
+//! bincode (Emacs `make-network-process`, socat one-liners).
+//!
+//! One command per connection:
+//!
+//! ```text
+//! COPY\n<payload until EOF>   ->  OK\n            | ERR <message>\n
+//! PASTE\n                     ->  OK\n<payload>   | ERR <message>\n
+//! ```
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use tracing::{info_span, warn};
+use zeroize::{Zeroize, Zeroizing};
+
+use clipto_ipc::{CopySource, Request, Response};
+
+use crate::{answer, peer_name, set_timeouts, throttle, Daemon, Metrics};
+
+/// Longest command line accepted before the payload starts.
+const MAX_COMMAND_LEN: usize = 16;
+
+/// What a payload buffer starts at, before it grows.
+const PAYLOAD_CHUNK: usize = 64 << 10;
+
+/// Serve the line protocol on `listener` from a background thread.
+pub fn start(listener: UnixListener, daemon: Arc<Daemon>) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let daemon = Arc::clone(&daemon);
+                    let span = info_span!("line_conn");
+                    std::thread::spawn(move || span.in_scope(|| handle(stream, &daemon)));
+                }
+                Err(e) => {
+                    Metrics::incr(&daemon.metrics.errors);
+                    warn!("line socket accept error: {e}");
+                }
+            }
+        }
+    });
+}
+
+fn handle(mut stream: UnixStream, daemon: &Arc<Daemon>) {
+    let client = peer_name(&stream);
+    let result = (|| -> Result<()> {
+        set_timeouts(&stream, daemon.io_timeout)?;
+        let request = match read_request(&mut stream, daemon.max_request) {
+            Ok(request) => request,
+            Err(e) => {
+                writeln!(stream, "ERR {e}")?;
+                return Err(e);
+            }
+        };
+
+        let mut response = answer(daemon, request, &client, throttle(daemon, &stream));
+        match &mut response {
+            Response::Ok => stream.write_all(b"OK\n")?,
+            Response::Payload { data } => {
+                stream.write_all(b"OK\n")?;
+                let written = stream.write_all(data);
+                data.zeroize();
+                written?;
+            }
+            Response::Error { message } => writeln!(stream, "ERR {message}")?,
+            Response::Throttled { retry_after_ms } => {
+                writeln!(stream, "ERR throttled; retry in {retry_after_ms}ms")?
+            }
+            _ => stream.write_all(b"ERR unexpected response\n")?,
+        }
+        stream.flush()?;
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        Metrics::incr(&daemon.metrics.errors);
+        warn!("line connection error: {e:#}");
+    }
+}
+
+/// Read a command and its payload straight from `stream`. Nothing is
+/// buffered ahead of what is parsed, so the only copy of a payload is the one
+/// returned.
+fn read_request(stream: &mut UnixStream, max: u32) -> Result<Request> {
+    let command = read_command(stream).context("failed to read command")?;
+    match command.strip_suffix(b"\n") {
+        Some(b"COPY") => {
+            let mut payload = read_payload(stream, max)?;
+            let payload = std::mem::take(&mut *payload);
+            Ok(Request::Copy { payload, source: CopySource::User, mime: None, once: false })
+        }
+        Some(b"PASTE") => Ok(Request::Paste),
+        _ => bail!("unknown command, expected COPY or PASTE"),
+    }
+}
+
+/// Up to `MAX_COMMAND_LEN` bytes, through the first newline. Read a byte at a
+/// time, so the payload after it stays unread.
+fn read_command(stream: &mut UnixStream) -> std::io::Result<Vec<u8>> {
+    let mut command = Vec::new();
+    let mut byte = [0u8];
+    while command.len() < MAX_COMMAND_LEN && command.last() != Some(&b'\n') {
+        match stream.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) => command.push(byte[0]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(command)
+}
+
+/// The rest of `stream`, up to `max` bytes. The buffer is never reallocated
+/// in place: when it fills, its content moves to one twice the size and the
+/// old one is zeroized, so freed memory keeps no copy of the payload.
+pub fn read_payload(stream: &mut impl Read, max: u32) -> Result<Zeroizing<Vec<u8>>> {
+    let limit = max as usize + 1;
+    let mut payload = Zeroizing::new(Vec::with_capacity(PAYLOAD_CHUNK.min(limit)));
+    loop {
+        if payload.len() > max as usize {
+            bail!("payload is over the {max}-byte limit");
+        }
+        if payload.len() == payload.capacity() {
+            let mut grown = Zeroizing::new(Vec::with_capacity((payload.len() * 2).min(limit)));
+            grown.extend_from_slice(&payload);
+            payload = grown;
+        }
+        let (len, capacity) = (payload.len(), payload.capacity());
+        payload.resize(capacity, 0);
+        match stream.read(&mut payload[len..]) {
+            Ok(0) => {
+                payload.truncate(len);
+                return Ok(payload);
+            }
+            Ok(n) => payload.truncate(len + n),
+            Err(e) if e.kind() == ErrorKind::Interrupted => payload.truncate(len),
+            Err(e) => return Err(e).context("failed to read payload"),
+        }
+    }
+}
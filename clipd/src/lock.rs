@@ -0,0 +1,89 @@
+//! Wipe the clipboard when the user walks away: logind's session `Lock`
+//! signal (screen locker engaged) and the manager's `PrepareForSleep(true)`.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use tracing::{debug, error, info, info_span};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::message::Type;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::MatchRule;
+
+use crate::Daemon;
+
+const LOGIND: &str = "org.freedesktop.login1";
+
+/// Spawn a thread listening to logind on the system bus. Only locks of this
+/// user's sessions trigger a wipe.
+pub fn start(daemon: Arc<Daemon>, on_lock: bool, on_sleep: bool) {
+    std::thread::spawn(move || {
+        let _span = info_span!("logind").entered();
+        if let Err(e) = run(&daemon, on_lock, on_sleep) {
+            error!("logind watcher stopped: {e:#}");
+        }
+    });
+}
+
+fn run(daemon: &Daemon, on_lock: bool, on_sleep: bool) -> Result<()> {
+    let conn = Connection::system().context("failed to connect to the system bus")?;
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender(LOGIND)?
+        .build();
+    let signals = MessageIterator::for_match_rule(rule, &conn, None)
+        .context("failed to subscribe to logind signals")?;
+
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    info!(on_lock, on_sleep, "watching logind");
+
+    for msg in signals {
+        let msg = msg.context("logind signal stream failed")?;
+        let header = msg.header();
+        let (Some(interface), Some(member)) = (header.interface(), header.member()) else {
+            continue;
+        };
+
+        let reason = match (interface.as_str(), member.as_str()) {
+            ("org.freedesktop.login1.Session", "Lock") if on_lock => {
+                let Some(path) = header.path() else { continue };
+                match session_uid(&conn, path.as_str()) {
+                    Ok(owner) if owner == uid => "session locked",
+                    Ok(_) => continue,
+                    Err(e) => {
+                        debug!("ignoring lock of {path}: {e:#}");
+                        continue;
+                    }
+                }
+            }
+            ("org.freedesktop.login1.Manager", "PrepareForSleep") if on_sleep => {
+                match msg.body().deserialize::<bool>() {
+                    Ok(true) => "system going to sleep",
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        info!("{reason}, wiping clipboard");
+        crate::clear(daemon, Some(reason));
+    }
+
+    Ok(())
+}
+
+/// Owner of a logind session, from its `User` property (`(uo)`).
+fn session_uid(conn: &Connection, path: &str) -> Result<u32> {
+    let reply = conn.call_method(
+        Some(LOGIND),
+        path,
+        Some("org.freedesktop.DBus.Properties"),
+        "Get",
+        &("org.freedesktop.login1.Session", "User"),
+    )?;
+    let value: OwnedValue = reply.body().deserialize()?;
+    let (uid, _user): (u32, OwnedObjectPath) =
+        value.try_into().context("unexpected User property type")?;
+    Ok(uid)
+}
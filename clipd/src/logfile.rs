@@ -0,0 +1,162 @@
+//! Log file with built-in rotation, for setups without journald.
+//!
+//! When the file grows past `max_bytes` (or gets older than `max_age_secs`)
+//! it is renamed to `<log_file>.<timestamp>`, a fresh file is opened, and a
+//! background thread gzips the old one and deletes all but the newest `keep`.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Deserialize;
+
+/// `[log_rotation]` config table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RotationConfig {
+    /// Rotate once the file would exceed this many bytes.
+    pub max_bytes: u64,
+    /// Also rotate once the file has been written to for this long.
+    pub max_age_secs: Option<u64>,
+    /// Rotated files kept; older ones are deleted.
+    pub keep: usize,
+    /// Gzip rotated files.
+    pub compress: bool,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self { max_bytes: 10 * 1024 * 1024, max_age_secs: None, keep: 5, compress: true }
+    }
+}
+
+pub struct RotatingFile {
+    path: PathBuf,
+    config: RotationConfig,
+    file: File,
+    size: u64,
+    opened: Instant,
+}
+
+impl RotatingFile {
+    pub fn open(path: PathBuf, config: RotationConfig) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, config, file, size, opened: Instant::now() })
+    }
+
+    fn due(&self, incoming: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_big = self.size + incoming as u64 > self.config.max_bytes;
+        let too_old = self
+            .config
+            .max_age_secs
+            .is_some_and(|secs| self.opened.elapsed() >= Duration::from_secs(secs));
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let stamp = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+        let mut rotated = OsString::from(self.path.as_os_str());
+        rotated.push(format!(".{stamp}"));
+        let rotated = PathBuf::from(rotated);
+
+        fs::rename(&self.path, &rotated)?;
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.opened = Instant::now();
+
+        let path = self.path.clone();
+        let config = self.config.clone();
+        std::thread::spawn(move || {
+            // Can't go through `tracing` from inside its writer.
+            if config.compress {
+                if let Err(e) = compress(&rotated) {
+                    eprintln!("clipd: failed to compress {}: {e}", rotated.display());
+                }
+            }
+            if let Err(e) = prune(&path, config.keep) {
+                eprintln!("clipd: failed to prune old logs: {e}");
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.due(buf.len()) {
+            if let Err(e) = self.rotate() {
+                eprintln!("clipd: log rotation failed: {e}");
+                // Keep writing to the current file; retry after another
+                // `max_bytes` rather than on every event.
+                self.size = 0;
+                self.opened = Instant::now();
+            }
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).mode(0o600).open(path)
+}
+
+/// Replace `path` with `path.gz`.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut gz_path = OsString::from(path.as_os_str());
+    gz_path.push(".gz");
+
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(open_append(Path::new(&gz_path))?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::remove_file(path)
+}
+
+/// Delete rotated copies of `log` beyond the newest `keep`. Timestamps sort
+/// lexicographically, and a file and its `.gz` count as one.
+fn prune(log: &Path, keep: usize) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (log.parent(), log.file_name()) else {
+        return Ok(());
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+
+    let mut rotated: Vec<(String, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(rest) = file_name.strip_prefix(&prefix) {
+            let stamp = rest.strip_suffix(".gz").unwrap_or(rest).to_owned();
+            rotated.push((stamp, entry.path()));
+        }
+    }
+
+    let mut stamps: Vec<&str> = rotated.iter().map(|(s, _)| s.as_str()).collect();
+    stamps.sort_unstable_by(|a, b| b.cmp(a));
+    stamps.dedup();
+    let kept: Vec<&str> = stamps.into_iter().take(keep).collect();
+
+    for (stamp, path) in &rotated {
+        if !kept.contains(&stamp.as_str()) {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter, Layer, Registry};
+
+use crate::config::Config;
+use crate::errorlog::{ErrorLog, ErrorLogLayer};
+use crate::logfile::RotatingFile;
+
+/// Install the global `tracing` subscriber and return the ring buffer of
+/// recent warnings and errors.
+///
+/// The filter comes from `RUST_LOG`, falling back to `log_level` in the config
+/// and then to `info`. With `log_file` set, events are written there
+/// (rotated in-process). Otherwise, under systemd (`$JOURNAL_STREAM` is set) events go to
+/// journald with native structured fields; otherwise they are formatted to
+/// stderr. The error ring always records `WARN` and above, whatever the
+/// output filter says.
+pub fn init(config: &Config) -> Arc<ErrorLog> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let directive = config.log_level.as_deref().unwrap_or("info");
+        EnvFilter::try_new(directive).unwrap_or_else(|e| {
+            eprintln!("invalid log_level {directive:?}: {e}, using info");
+            EnvFilter::new("info")
+        })
+    });
+
+    let errors = Arc::new(ErrorLog::default());
+
+    tracing_subscriber::registry()
+        .with(output_layer(config).with_filter(filter))
+        .with(ErrorLogLayer(Arc::clone(&errors)).with_filter(LevelFilter::WARN))
+        .init();
+
+    errors
+}
+
+fn output_layer(config: &Config) -> Box<dyn Layer<Registry> + Send + Sync> {
+    if let Some(path) = &config.log_file {
+        match RotatingFile::open(path.clone(), config.log_rotation.clone()) {
+            Ok(file) => return fmt::layer().with_writer(Mutex::new(file)).with_ansi(false).boxed(),
+            Err(e) => eprintln!("can't open log file {}, logging to stderr: {e}", path.display()),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("JOURNAL_STREAM").is_some() {
+        match tracing_journald::layer() {
+            Ok(journald) => return journald.with_syslog_identifier("clipd".into()).boxed(),
+            Err(e) => eprintln!("journald unavailable, logging to stderr: {e}"),
+        }
+    }
+
+    fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .boxed()
+}
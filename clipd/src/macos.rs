@@ -0,0 +1,164 @@
+//! The macOS backend: the general pasteboard. `pbcopy` sets it; a
+//! long-running `osascript` reports each change of the pasteboard's change
+//! count, the way `wl-paste --watch` does under Wayland, and `pbpaste` fetches
+//! the new content.
+//!
+//! Only text crosses over: `pbcopy` and `pbpaste` don't handle other types.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use clipto_ipc::{CopySource, Request, Response, Status, WatcherState};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::backend::Backend;
+use crate::supervise::Supervisor;
+use crate::{dispatch, Daemon};
+
+/// `[macos]` config table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MacosConfig {
+    /// How often the watcher checks the pasteboard's change count, in
+    /// milliseconds.
+    pub poll_interval_ms: u64,
+}
+
+impl Default for MacosConfig {
+    fn default() -> Self {
+        Self { poll_interval_ms: 250 }
+    }
+}
+
+/// NSPasteboard has no change notification, only a counter that increments
+/// on every write, so this polls it. Prints the count on each change.
+const WATCH_SCRIPT: &str = r#"
+ObjC.import('AppKit');
+const pasteboard = $.NSPasteboard.generalPasteboard;
+const stdout = $.NSFileHandle.fileHandleWithStandardOutput;
+let last = null;
+while (true) {
+    const count = pasteboard.changeCount;
+    if (count !== last) {
+        last = count;
+        stdout.writeData($(count + '\n').dataUsingEncoding($.NSUTF8StringEncoding));
+    }
+    delay(@POLL@ / 1000);
+}
+"#;
+
+pub struct Macos {
+    config: MacosConfig,
+    state: Arc<Mutex<WatcherState>>,
+}
+
+impl Macos {
+    pub fn new(config: MacosConfig) -> Self {
+        Self { config, state: Arc::new(Mutex::new(WatcherState::Stopped)) }
+    }
+}
+
+impl Backend for Macos {
+    fn name(&self) -> &'static str {
+        "pasteboard"
+    }
+
+    fn set(&self, payload: &[u8], mime: Option<&str>) -> Result<bool> {
+        if mime.is_some_and(|m| !m.starts_with("text/")) || std::str::from_utf8(payload).is_err() {
+            return Ok(false);
+        }
+        pbcopy(payload)?;
+        Ok(true)
+    }
+
+    fn clear(&self) -> Result<()> {
+        pbcopy(&[])
+    }
+
+    /// The pasteboard keeps its content after we exit.
+    fn persist(&self, _payload: &[u8], _mime: Option<&str>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Runs for the daemon's lifetime: the supervisor is never stopped.
+    fn start_watcher(&self, daemon: Arc<Daemon>) {
+        let script = WATCH_SCRIPT.replace("@POLL@", &self.config.poll_interval_ms.to_string());
+        Supervisor::start(
+            "osascript pasteboard watcher",
+            Arc::clone(&self.state),
+            move || {
+                let mut cmd = Command::new("osascript");
+                cmd.args(["-l", "JavaScript", "-e", &script])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped());
+                cmd
+            },
+            move |child| watch(&daemon, child),
+        );
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            backend: self.name().to_owned(),
+            display: None,
+            watcher: self.state.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Store the pasteboard each time an `osascript` watcher reports that its
+/// change count moved, until the watcher's output ends.
+fn watch(daemon: &Arc<Daemon>, child: &mut Child) -> Result<()> {
+    let stdout = child.stdout.take().context("no stdout")?;
+    for line in BufReader::new(stdout).lines() {
+        line.context("failed to read from osascript")?;
+        let payload = pbpaste()?;
+        if payload.is_empty() {
+            continue; // cleared, or holding something other than text
+        }
+        let request = Request::Copy {
+            payload,
+            source: CopySource::Wayland,
+            mime: Some("text/plain;charset=utf-8".to_owned()),
+            once: false,
+        };
+        if let Response::Error { message } = dispatch(daemon, request, "pasteboard") {
+            warn!("pasteboard change not stored: {message}");
+        }
+    }
+    Ok(())
+}
+
+/// `pbcopy` and `pbpaste` read and write in the locale's encoding, and
+/// launchd agents start without one, so pin it to UTF-8.
+fn pb(program: &str) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.env("LANG", "en_US.UTF-8");
+    cmd
+}
+
+fn pbcopy(input: &[u8]) -> Result<()> {
+    let mut child = pb("pbcopy")
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("failed to spawn pbcopy")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).context("failed to write to pbcopy")?;
+    }
+    let status = child.wait().context("pbcopy failed")?;
+    if !status.success() {
+        bail!("pbcopy exited with {status}");
+    }
+    Ok(())
+}
+
+fn pbpaste() -> Result<Vec<u8>> {
+    let out = pb("pbpaste").output().context("failed to run pbpaste")?;
+    if !out.status.success() {
+        bail!("pbpaste exited with {}", out.status);
+    }
+    Ok(out.stdout)
+}
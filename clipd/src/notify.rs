@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tracing::{debug, warn};
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+use crate::compositor;
+
+/// Which clipboard events raise a desktop notification. All off by default.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Copies from the user (tmux, scripts, `clipto copy`).
+    pub copy: bool,
+    /// Copies synced in from the Wayland compositor.
+    pub wayland_copy: bool,
+    /// `clipto clear`, and wipes on lock or sleep.
+    pub cleared: bool,
+    /// Stay quiet while the focused window is in any of these states.
+    pub quiet_when: Vec<Quiet>,
+}
+
+/// Focused-window states that suppress notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quiet {
+    /// A fullscreen window (games, presentations, video).
+    Fullscreen,
+    /// A window holding an idle inhibitor (sway only).
+    IdleInhibit,
+}
+
+/// Something worth telling the user about.
+#[derive(Debug)]
+pub enum Event {
+    Copied { bytes: usize, mime: String },
+    /// `reason` is set for wipes, e.g. "session locked".
+    Cleared { reason: Option<&'static str> },
+}
+
+impl Event {
+    fn summary(&self) -> String {
+        match self {
+            Event::Copied { bytes, mime } => format!("Copied {} ({mime})", human_size(*bytes)),
+            Event::Cleared { reason: None } => "Clipboard cleared".to_owned(),
+            Event::Cleared { reason: Some(reason) } => format!("Clipboard cleared ({reason})"),
+        }
+    }
+}
+
+/// Handle to the notification thread. Sending never blocks the caller; if the
+/// session bus is unreachable the events are logged and dropped.
+#[derive(Clone)]
+pub struct Notifier {
+    config: NotifyConfig,
+    tx: Sender<Event>,
+}
+
+impl Notifier {
+    pub fn start(config: NotifyConfig) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let quiet_when = config.quiet_when.clone();
+        std::thread::spawn(move || run(rx, quiet_when));
+        Self { config, tx }
+    }
+
+    pub fn copied(&self, bytes: usize, from_wayland: bool, mime: &str) {
+        let enabled = if from_wayland { self.config.wayland_copy } else { self.config.copy };
+        if enabled {
+            let _ = self.tx.send(Event::Copied { bytes, mime: mime.to_owned() });
+        }
+    }
+
+    pub fn cleared(&self, reason: Option<&'static str>) {
+        if self.config.cleared {
+            let _ = self.tx.send(Event::Cleared { reason });
+        }
+    }
+}
+
+/// Whether the focused window is in a state the user asked not to disturb.
+fn should_stay_quiet(quiet_when: &[Quiet]) -> bool {
+    if quiet_when.is_empty() {
+        return false;
+    }
+    let Some(focus) = compositor::focused() else {
+        return false;
+    };
+    quiet_when.iter().any(|q| match q {
+        Quiet::Fullscreen => focus.fullscreen,
+        Quiet::IdleInhibit => focus.idle_inhibit,
+    })
+}
+
+fn run(rx: Receiver<Event>, quiet_when: Vec<Quiet>) {
+    let mut conn: Option<Connection> = None;
+    // Reuse the previous notification id so a burst of copies updates one
+    // popup instead of stacking many.
+    let mut replaces_id = 0u32;
+
+    for event in rx {
+        if should_stay_quiet(&quiet_when) {
+            debug!(?event, "notification suppressed by focused window");
+            continue;
+        }
+
+        if conn.is_none() {
+            match Connection::session() {
+                Ok(c) => conn = Some(c),
+                Err(e) => {
+                    warn!("notifications: session bus unavailable: {e}");
+                    continue;
+                }
+            }
+        }
+
+        let summary = event.summary();
+        match send(conn.as_ref().unwrap(), replaces_id, &summary) {
+            Ok(id) => {
+                debug!(id, "notification sent");
+                replaces_id = id;
+            }
+            Err(e) => {
+                warn!("notifications: {e:#}");
+                // Drop the connection so the next event reconnects.
+                conn = None;
+            }
+        }
+    }
+}
+
+fn send(conn: &Connection, replaces_id: u32, summary: &str) -> Result<u32> {
+    let hints: HashMap<&str, Value> = HashMap::new();
+    let reply = conn
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &("clipto", replaces_id, "edit-paste", summary, "", Vec::<&str>::new(), hints, -1i32),
+        )
+        .context("Notify call failed")?;
+    reply.body().deserialize().context("unexpected Notify reply")
+}
+
+/// Best-effort MIME guess for notification text when a copy carries no type.
+pub fn sniff_mime(payload: &[u8]) -> &'static str {
+    if std::str::from_utf8(payload).is_ok() {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,430 @@
+//! The portal backend: the desktop clipboard through xdg-desktop-portal's
+//! `Clipboard` interface, for compositors without `zwlr_data_control`
+//! (GNOME's Mutter), where `wl-paste --watch` only sees the clipboard while a
+//! window of its own has focus.
+//!
+//! The portal only hands out the clipboard inside a `RemoteDesktop` session,
+//! which the user approves in a dialog once. The approval is kept as a
+//! restore token in the runtime directory, so daemon restarts don't ask
+//! again; a new login does.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use clipto_ipc::{CopySource, Request, Response, Status, WatcherState};
+use tracing::{debug, error, info, info_span, warn};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::message::Type;
+use zbus::zvariant::{self, ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::MatchRule;
+use zeroize::Zeroizing;
+
+use crate::backend::Backend;
+use crate::config::WatchConfig;
+use crate::{dispatch, line, unix_secs, Daemon};
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const REMOTE_DESKTOP: &str = "org.freedesktop.portal.RemoteDesktop";
+const CLIPBOARD: &str = "org.freedesktop.portal.Clipboard";
+
+/// `SelectDevices` types: the portal wants at least one device, and a
+/// keyboard is the least it can be. No input is ever sent.
+const KEYBOARD: u32 = 1;
+/// `SelectDevices` persist mode: until the user revokes it.
+const PERSIST_UNTIL_REVOKED: u32 = 2;
+/// `Request::Response` code for a dialog the user dismissed.
+const CANCELLED: u32 = 1;
+
+const MIN_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(60);
+
+/// What plain text is offered as, the way `wl-copy` offers it.
+const TEXT_TYPES: [&str; 5] =
+    ["text/plain;charset=utf-8", "text/plain", "UTF8_STRING", "STRING", "TEXT"];
+
+/// Whether the auto-detected backend should be this one: under GNOME, whose
+/// compositor has no data-control protocol for `wl-paste --watch`.
+pub fn preferred() -> bool {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .is_ok_and(|d| d.split(':').any(|d| d.eq_ignore_ascii_case("gnome")))
+}
+
+pub struct Portal {
+    watch: WatchConfig,
+    state: Arc<Mutex<WatcherState>>,
+    /// The approved session, while there is one.
+    session: Arc<Mutex<Option<Session>>>,
+    /// What clipd put in the desktop clipboard, served on each paste.
+    offer: Arc<Mutex<Option<Offer>>>,
+}
+
+#[derive(Clone)]
+struct Session {
+    conn: Connection,
+    handle: OwnedObjectPath,
+}
+
+struct Offer {
+    payload: Arc<Zeroizing<Vec<u8>>>,
+    types: Vec<String>,
+}
+
+impl Portal {
+    pub fn new(watch: WatchConfig) -> Self {
+        Self {
+            watch,
+            state: Arc::new(Mutex::new(WatcherState::Stopped)),
+            session: Arc::default(),
+            offer: Arc::default(),
+        }
+    }
+
+    /// Offer `types` as the desktop clipboard, or nothing when empty.
+    fn set_selection(&self, types: &[String]) -> Result<bool> {
+        let Some(session) = self.session.lock().unwrap().clone() else {
+            return Ok(false);
+        };
+        let options = HashMap::from([("mime_types", Value::from(types.to_vec()))]);
+        session
+            .conn
+            .call_method(Some(PORTAL), PORTAL_PATH, Some(CLIPBOARD), "SetSelection", &(
+                &session.handle,
+                options,
+            ))
+            .context("SetSelection failed")?;
+        Ok(true)
+    }
+}
+
+impl Backend for Portal {
+    fn name(&self) -> &'static str {
+        "portal"
+    }
+
+    fn set(&self, payload: &[u8], mime: Option<&str>) -> Result<bool> {
+        if self.session.lock().unwrap().is_none() {
+            return Ok(false);
+        }
+        let types = match mime {
+            Some(m) if !m.starts_with("text/plain") => vec![m.to_owned()],
+            None if std::str::from_utf8(payload).is_err() => {
+                vec!["application/octet-stream".to_owned()]
+            }
+            _ => TEXT_TYPES.map(String::from).to_vec(),
+        };
+        let payload = Arc::new(Zeroizing::new(payload.to_vec()));
+        *self.offer.lock().unwrap() = Some(Offer { payload, types: types.clone() });
+        self.set_selection(&types)
+    }
+
+    fn clear(&self) -> Result<()> {
+        *self.offer.lock().unwrap() = None;
+        self.set_selection(&[])?;
+        Ok(())
+    }
+
+    /// The selection belongs to the portal session, which ends with clipd.
+    fn persist(&self, _payload: &[u8], _mime: Option<&str>) -> Result<()> {
+        debug!("the portal clipboard can't outlive clipd");
+        Ok(())
+    }
+
+    /// Runs for the daemon's lifetime. A session the portal closes or loses
+    /// is set up again, with backoff; one the user declined is not.
+    fn start_watcher(&self, daemon: Arc<Daemon>) {
+        let (watch, state) = (self.watch.clone(), Arc::clone(&self.state));
+        let (session, offer) = (Arc::clone(&self.session), Arc::clone(&self.offer));
+        std::thread::spawn(move || {
+            let _span = info_span!("portal").entered();
+            let mut restarts = 0;
+            let mut retry = MIN_RETRY;
+            loop {
+                let result = run(&daemon, &watch, &state, &session, &offer, restarts);
+                *session.lock().unwrap() = None;
+                let last_exit = match result {
+                    Err(e) if e.is::<Declined>() => {
+                        error!("{e}; restart clipd to ask again");
+                        *state.lock().unwrap() = WatcherState::Stopped;
+                        return;
+                    }
+                    Err(e) => format!("{e:#}"),
+                    Ok(()) => "the portal closed the session".to_owned(),
+                };
+                restarts += 1;
+                warn!("portal session ended: {last_exit}; retrying in {}s", retry.as_secs());
+                *state.lock().unwrap() = WatcherState::Restarting {
+                    restarts,
+                    last_exit,
+                    retry_unix: unix_secs(SystemTime::now() + retry),
+                };
+                std::thread::sleep(retry);
+                retry = (retry * 2).min(MAX_RETRY);
+            }
+        });
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            backend: self.name().to_owned(),
+            display: None,
+            watcher: self.state.lock().unwrap().clone(),
+        }
+    }
+
+    fn programs(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// The user dismissed the portal's dialog.
+#[derive(Debug)]
+struct Declined;
+
+impl std::fmt::Display for Declined {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("clipboard access was declined in the portal dialog")
+    }
+}
+
+impl std::error::Error for Declined {}
+
+/// Set up a session, then serve it until the portal closes it.
+fn run(
+    daemon: &Arc<Daemon>,
+    watch: &WatchConfig,
+    state: &Mutex<WatcherState>,
+    shared: &Mutex<Option<Session>>,
+    offer: &Arc<Mutex<Option<Offer>>>,
+    restarts: u32,
+) -> Result<()> {
+    let conn = Connection::session().context("failed to connect to the session bus")?;
+    // Subscribed before the first call, so no response can slip past.
+    let rule = MatchRule::builder().msg_type(Type::Signal).sender(PORTAL)?.build();
+    let mut signals = MessageIterator::for_match_rule(rule, &conn, None)
+        .context("failed to subscribe to portal signals")?;
+
+    let handle = start_session(&conn, &mut signals)?;
+    info!("portal clipboard session started");
+    *state.lock().unwrap() = WatcherState::Running {
+        pid: std::process::id(),
+        since_unix: unix_secs(SystemTime::now()),
+        restarts,
+    };
+    *shared.lock().unwrap() = Some(Session { conn: conn.clone(), handle: handle.clone() });
+
+    for msg in signals {
+        let msg = msg.context("portal signal stream failed")?;
+        let header = msg.header();
+        let (Some(interface), Some(member)) = (header.interface(), header.member()) else {
+            continue;
+        };
+        match (interface.as_str(), member.as_str()) {
+            (CLIPBOARD, "SelectionOwnerChanged") => {
+                let (session, options): (OwnedObjectPath, HashMap<String, OwnedValue>) =
+                    msg.body().deserialize()?;
+                if session != handle || option::<bool>(&options, "session_is_owner") == Some(true)
+                {
+                    continue;
+                }
+                let offered = option::<Vec<String>>(&options, "mime_types").unwrap_or_default();
+                let offered: Vec<&str> = offered.iter().map(String::as_str).collect();
+                let Some(mime) = clipto_ipc::choose_type(&offered, &watch.prefer, &watch.deny)
+                else {
+                    debug!(?offered, "no acceptable type offered");
+                    continue;
+                };
+                if let Err(e) = store(daemon, &conn, &handle, mime) {
+                    warn!("desktop clipboard change not stored: {e:#}");
+                }
+            }
+            (CLIPBOARD, "SelectionTransfer") => {
+                let (session, mime, serial): (OwnedObjectPath, String, u32) =
+                    msg.body().deserialize()?;
+                if session != handle {
+                    continue;
+                }
+                let payload = offer
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .filter(|o| o.types.contains(&mime))
+                    .map(|o| Arc::clone(&o.payload));
+                let (conn, handle) = (conn.clone(), handle.clone());
+                // A slow reader on the other end mustn't hold up the signals.
+                std::thread::spawn(move || {
+                    if let Err(e) = transfer(&conn, &handle, serial, payload.as_deref()) {
+                        warn!("paste from the desktop failed: {e:#}");
+                    }
+                });
+            }
+            ("org.freedesktop.portal.Session", "Closed") if header.path() == Some(&handle) => {
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+    bail!("the session bus connection closed")
+}
+
+/// `CreateSession`, `SelectDevices`, `RequestClipboard` and `Start`, in the
+/// order the portal wants them. Returns the session handle.
+fn start_session(conn: &Connection, signals: &mut MessageIterator) -> Result<OwnedObjectPath> {
+    let results = request(signals, REMOTE_DESKTOP, "CreateSession", |token| {
+        let options = HashMap::from([
+            ("handle_token", Value::from(token)),
+            ("session_handle_token", Value::from(token)),
+        ]);
+        conn.call_method(Some(PORTAL), PORTAL_PATH, Some(REMOTE_DESKTOP), "CreateSession", &(
+            options,
+        ))
+    })?;
+    let handle = option::<String>(&results, "session_handle")
+        .context("CreateSession returned no session handle")?;
+    let handle = OwnedObjectPath::try_from(handle).context("invalid session handle")?;
+
+    let restore_token = token_path().and_then(|p| std::fs::read_to_string(p).ok());
+    request(signals, REMOTE_DESKTOP, "SelectDevices", |token| {
+        let mut options = HashMap::from([
+            ("handle_token", Value::from(token)),
+            ("types", Value::from(KEYBOARD)),
+            ("persist_mode", Value::from(PERSIST_UNTIL_REVOKED)),
+        ]);
+        if let Some(restore) = restore_token.as_deref() {
+            options.insert("restore_token", Value::from(restore.trim()));
+        }
+        conn.call_method(Some(PORTAL), PORTAL_PATH, Some(REMOTE_DESKTOP), "SelectDevices", &(
+            &handle,
+            options,
+        ))
+    })?;
+
+    let options: HashMap<&str, Value> = HashMap::new();
+    conn.call_method(Some(PORTAL), PORTAL_PATH, Some(CLIPBOARD), "RequestClipboard", &(
+        &handle,
+        options,
+    ))
+    .context("RequestClipboard failed")?;
+
+    let results = request(signals, REMOTE_DESKTOP, "Start", |token| {
+        let options = HashMap::from([("handle_token", Value::from(token))]);
+        conn.call_method(Some(PORTAL), PORTAL_PATH, Some(REMOTE_DESKTOP), "Start", &(
+            &handle,
+            "",
+            options,
+        ))
+    })?;
+    if option::<bool>(&results, "clipboard_enabled") != Some(true) {
+        bail!("the portal started the session without clipboard access");
+    }
+    if let (Some(token), Some(path)) = (option::<String>(&results, "restore_token"), token_path())
+    {
+        if let Err(e) = std::fs::write(&path, token) {
+            warn!("failed to save the portal restore token to {}: {e}", path.display());
+        }
+    }
+    Ok(handle)
+}
+
+/// Make a portal call that answers through a `Request` object, and wait for
+/// its `Response`. `call` gets the handle token to pass.
+fn request(
+    signals: &mut MessageIterator,
+    interface: &str,
+    method: &str,
+    call: impl FnOnce(&str) -> zbus::Result<zbus::Message>,
+) -> Result<HashMap<String, OwnedValue>> {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    let token = format!("clipto{}", NEXT.fetch_add(1, Ordering::Relaxed));
+    let reply = call(&token).with_context(|| format!("{method} failed"))?;
+    let path: OwnedObjectPath = reply.body().deserialize()?;
+
+    for msg in signals.by_ref() {
+        let msg = msg.context("portal signal stream failed")?;
+        let header = msg.header();
+        let is_response = header.interface().is_some_and(|i| i == "org.freedesktop.portal.Request")
+            && header.member().is_some_and(|m| m == "Response")
+            && header.path() == Some(&path);
+        if !is_response {
+            continue;
+        }
+        let (code, results): (u32, HashMap<String, OwnedValue>) = msg.body().deserialize()?;
+        return match code {
+            0 => Ok(results),
+            CANCELLED => Err(Declined.into()),
+            code => bail!("{interface}.{method} failed with response {code}"),
+        };
+    }
+    bail!("the session bus connection closed")
+}
+
+/// Fetch the desktop clipboard as `mime` and store it.
+fn store(daemon: &Arc<Daemon>, conn: &Connection, handle: &ObjectPath, mime: &str) -> Result<()> {
+    let reply = conn
+        .call_method(Some(PORTAL), PORTAL_PATH, Some(CLIPBOARD), "SelectionRead", &(handle, mime))
+        .context("SelectionRead failed")?;
+    let fd: zvariant::OwnedFd = reply.body().deserialize()?;
+    // Any app can set the selection, so it is held to the same limit as a
+    // request over the socket.
+    let mut payload = line::read_payload(&mut File::from(OwnedFd::from(fd)), daemon.max_request)
+        .context("failed to read the desktop clipboard")?;
+    let request = Request::Copy {
+        payload: std::mem::take(&mut *payload),
+        source: CopySource::Wayland,
+        mime: Some(mime.to_owned()),
+        once: false,
+    };
+    if let Response::Error { message } = dispatch(daemon, request, "portal") {
+        bail!("{message}");
+    }
+    Ok(())
+}
+
+/// Answer a paste: write `payload` to the fd the portal hands out for
+/// `serial`, then say whether that worked. `None` fails the paste.
+fn transfer(
+    conn: &Connection,
+    handle: &ObjectPath,
+    serial: u32,
+    payload: Option<&Zeroizing<Vec<u8>>>,
+) -> Result<()> {
+    let written = match payload {
+        Some(payload) => (|| -> Result<()> {
+            let reply = conn
+                .call_method(Some(PORTAL), PORTAL_PATH, Some(CLIPBOARD), "SelectionWrite", &(
+                    handle, serial,
+                ))
+                .context("SelectionWrite failed")?;
+            let fd: zvariant::OwnedFd = reply.body().deserialize()?;
+            File::from(OwnedFd::from(fd))
+                .write_all(payload)
+                .context("failed to write to the desktop clipboard")
+        })(),
+        None => Err(anyhow::anyhow!("nothing to offer in the requested type")),
+    };
+    conn.call_method(Some(PORTAL), PORTAL_PATH, Some(CLIPBOARD), "SelectionWriteDone", &(
+        handle,
+        serial,
+        written.is_ok(),
+    ))
+    .context("SelectionWriteDone failed")?;
+    written
+}
+
+/// A value from an `a{sv}` of portal results, if present with type `T`.
+fn option<T: TryFrom<OwnedValue>>(options: &HashMap<String, OwnedValue>, key: &str) -> Option<T> {
+    T::try_from(options.get(key)?.try_clone().ok()?).ok()
+}
+
+/// Where the session's restore token is kept: `clipto-portal.token` in the
+/// runtime directory, which the sandbox leaves writable.
+fn token_path() -> Option<PathBuf> {
+    Some(clipto_ipc::runtime_dir().ok()?.join("clipto-portal.token"))
+}
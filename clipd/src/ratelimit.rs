@@ -0,0 +1,284 @@
+//! Token-bucket request limits, so a runaway client (a script looping
+//! `clipto copy`) is slowed down without slowing down everyone else.
+//!
+//! Off unless `[rate_limit] enabled` is set. Each request then takes a token
+//! from two buckets: its client's and its user's. By default a client is the
+//! peer's session (`getsid`) rather than its PID: a shell loop starts a new
+//! `clipto` each iteration, so per-PID buckets would start full every time
+//! and never slow it down, but the loop's processes all share the
+//! terminal's session, while other terminals, tmux panes and clipd's own
+//! watcher have sessions of their own. `client = "pid"` keys buckets by PID
+//! instead, for long-lived clients that make every request from one process
+//! (editor plugins, programs using the Node.js or C bindings). The user
+//! bucket caps the total when several users share an abstract socket.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+/// `[rate_limit]` config table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// What one client bucket covers.
+    pub client: ClientKey,
+    /// Sustained requests per second for one client.
+    pub per_second: f64,
+    /// Requests a client can make in a burst before `per_second` applies.
+    pub burst: u32,
+    /// The same two limits for all of one user's clients together.
+    pub uid_per_second: f64,
+    pub uid_burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            client: ClientKey::Session,
+            per_second: 20.0,
+            burst: 50,
+            uid_per_second: 100.0,
+            uid_burst: 200,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Reject limits no request could meet: with a rate that isn't positive,
+    /// or an empty bucket, every request would wait `MAX_WAIT`.
+    pub fn validate(&self) -> Result<()> {
+        let rates = [("per_second", self.per_second), ("uid_per_second", self.uid_per_second)];
+        for (key, rate) in rates {
+            if !(rate.is_finite() && rate > 0.0) {
+                bail!("[rate_limit] {key} must be a positive number, not {rate}");
+            }
+        }
+        for (key, burst) in [("burst", self.burst), ("uid_burst", self.uid_burst)] {
+            if burst == 0 {
+                bail!("[rate_limit] {key} must be at least 1");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How requests are grouped into clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientKey {
+    /// The peer's session: every process started from one terminal.
+    Session,
+    /// The peer process itself.
+    Pid,
+}
+
+/// Past this many buckets, full ones (idle clients) are dropped.
+const MAX_BUCKETS: usize = 256;
+
+/// Never ask a client to wait longer than this.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Client(u32),
+    Uid(u32),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<Key, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self { config, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Which client `config.client` says the peer `pid` belongs to.
+    pub fn client_of(&self, pid: libc::pid_t) -> u32 {
+        match self.config.client {
+            ClientKey::Pid => pid as u32,
+            // SAFETY: plain syscall.
+            ClientKey::Session => match unsafe { libc::getsid(pid) } {
+                -1 => pid as u32, // already gone; its PID is the best key left
+                sid => sid as u32,
+            },
+        }
+    }
+
+    /// Take a token for a request from user `uid` and client `client` (from
+    /// `client_of`), or say how long until there is one. Nothing is taken
+    /// when either bucket is empty.
+    pub fn check(&self, uid: u32, client: u32) -> Result<(), Duration> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_BUCKETS {
+            buckets.retain(|&key, bucket| {
+                let (rate, burst) = self.limits(key);
+                bucket.refill(now, rate, burst) < burst
+            });
+        }
+
+        let keys = [Key::Client(client), Key::Uid(uid)];
+        let mut wait = Duration::ZERO;
+        for key in keys {
+            let (rate, burst) = self.limits(key);
+            let bucket = buckets.entry(key).or_insert(Bucket { tokens: burst, updated: now });
+            let tokens = bucket.refill(now, rate, burst);
+            if tokens < 1.0 {
+                let secs = (1.0 - tokens) / rate;
+                wait = wait.max(Duration::try_from_secs_f64(secs).unwrap_or(MAX_WAIT));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait.min(MAX_WAIT));
+        }
+        for key in keys {
+            if let Some(bucket) = buckets.get_mut(&key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Refill rate and capacity of `key`'s bucket.
+    fn limits(&self, key: Key) -> (f64, f64) {
+        let c = &self.config;
+        match key {
+            Key::Client(_) => (c.per_second, c.burst as f64),
+            Key::Uid(_) => (c.uid_per_second, c.uid_burst as f64),
+        }
+    }
+}
+
+impl Bucket {
+    /// Add what `rate` earned since the last update, up to `burst`, and
+    /// return the new level.
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) -> f64 {
+        let earned = now.duration_since(self.updated).as_secs_f64() * rate;
+        self.tokens = (self.tokens + earned).min(burst);
+        self.updated = now;
+        self.tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_second: f64, burst: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            per_second,
+            burst,
+            uid_per_second: 1000.0,
+            uid_burst: 1000,
+            ..RateLimitConfig::default()
+        })
+    }
+
+    /// Pretend every bucket was last updated `ago`.
+    fn rewind(limiter: &RateLimiter, ago: Duration) {
+        for bucket in limiter.buckets.lock().unwrap().values_mut() {
+            bucket.updated -= ago;
+        }
+    }
+
+    #[test]
+    fn unmeetable_limits_are_rejected() {
+        assert!(RateLimitConfig::default().validate().is_ok());
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let config = RateLimitConfig { per_second: rate, ..RateLimitConfig::default() };
+            assert!(config.validate().is_err(), "{rate}");
+            let config = RateLimitConfig { uid_per_second: rate, ..RateLimitConfig::default() };
+            assert!(config.validate().is_err(), "{rate}");
+        }
+        assert!(RateLimitConfig { burst: 0, ..RateLimitConfig::default() }.validate().is_err());
+        assert!(RateLimitConfig { uid_burst: 0, ..RateLimitConfig::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn disabled_never_limits() {
+        let limiter = RateLimiter::new(RateLimitConfig { burst: 0, ..RateLimitConfig::default() });
+        assert!((0..1000).all(|_| limiter.check(1000, 1).is_ok()));
+    }
+
+    #[test]
+    fn burst_then_wait() {
+        let limiter = limiter(2.0, 3);
+        for _ in 0..3 {
+            assert_eq!(limiter.check(1000, 1), Ok(()));
+        }
+        let wait = limiter.check(1000, 1).unwrap_err();
+        let expected = Duration::from_millis(400)..=Duration::from_millis(500);
+        assert!(expected.contains(&wait), "{wait:?}");
+    }
+
+    #[test]
+    fn clients_have_their_own_buckets() {
+        let limiter = limiter(1.0, 1);
+        assert!(limiter.check(1000, 1).is_ok());
+        assert!(limiter.check(1000, 1).is_err());
+        assert!(limiter.check(1000, 2).is_ok());
+    }
+
+    #[test]
+    fn user_bucket_caps_all_clients() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            uid_per_second: 1.0,
+            uid_burst: 2,
+            ..RateLimitConfig::default()
+        });
+        assert!(limiter.check(1000, 1).is_ok());
+        assert!(limiter.check(1000, 2).is_ok());
+        assert!(limiter.check(1000, 3).is_err());
+        assert!(limiter.check(1001, 3).is_ok());
+    }
+
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let limiter = limiter(2.0, 3);
+        for _ in 0..3 {
+            limiter.check(1000, 1).unwrap();
+        }
+        rewind(&limiter, Duration::from_secs(1));
+        assert!(limiter.check(1000, 1).is_ok());
+        assert!(limiter.check(1000, 1).is_ok());
+        assert!(limiter.check(1000, 1).is_err());
+
+        rewind(&limiter, Duration::from_secs(3600));
+        assert!((0..3).all(|_| limiter.check(1000, 1).is_ok()));
+        assert!(limiter.check(1000, 1).is_err());
+    }
+
+    #[test]
+    fn rejected_request_takes_no_token() {
+        let limiter = limiter(1.0, 1);
+        limiter.check(1000, 1).unwrap();
+        for _ in 0..10 {
+            assert!(limiter.check(1000, 1).is_err());
+        }
+        // One second refills the one token the first request took, no more.
+        rewind(&limiter, Duration::from_secs(1));
+        assert!(limiter.check(1000, 1).is_ok());
+        assert!(limiter.check(1000, 1).is_err());
+    }
+}
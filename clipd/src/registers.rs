@@ -0,0 +1,124 @@
+//! Named registers scoped to a client-chosen session (a tmux pane, a project
+//! shell). Registers live outside the history, are never synced to the
+//! compositor, and are wiped once the process that opened the session exits.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tracing::{debug, info};
+
+use clipto_ipc::Session;
+
+use crate::history::EncryptedBuffer;
+use crate::Daemon;
+
+/// How often sessions are checked for a dead owner.
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub struct Registers {
+    sessions: HashMap<String, SessionRegisters>,
+}
+
+struct SessionRegisters {
+    owner: Owner,
+    entries: HashMap<String, EncryptedBuffer>,
+}
+
+impl Registers {
+    /// Store `buf` as register `name` of `session`, opening the session if
+    /// needed. The owner PID is fixed when the session opens; later requests
+    /// naming another PID (a subshell, an editor) join it.
+    pub fn set(&mut self, session: &Session, name: &str, buf: EncryptedBuffer) -> Result<()> {
+        let regs = match self.sessions.get_mut(&session.id) {
+            Some(regs) => regs,
+            None => {
+                let Some(owner) = Owner::of(session.pid) else {
+                    bail!("session owner {} is not running", session.pid);
+                };
+                debug!(session = %session.id, pid = session.pid, "session opened");
+                self.sessions
+                    .entry(session.id.clone())
+                    .or_insert(SessionRegisters { owner, entries: HashMap::new() })
+            }
+        };
+        regs.entries.insert(name.to_owned(), buf);
+        Ok(())
+    }
+
+    pub fn get(&self, session: &str, name: &str) -> Option<&EncryptedBuffer> {
+        self.sessions.get(session)?.entries.get(name)
+    }
+
+    /// Drop every session whose owner has exited.
+    fn reap(&mut self) {
+        self.sessions.retain(|id, regs| {
+            let alive = regs.owner.alive();
+            if !alive {
+                info!(session = %id, registers = regs.entries.len(), "session ended, registers wiped");
+            }
+            alive
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.sessions.clear();
+    }
+}
+
+/// Check sessions for dead owners from a background thread.
+pub fn start_reaper(daemon: Arc<Daemon>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(REAP_INTERVAL);
+        daemon.state.lock().unwrap().registers.reap();
+    });
+}
+
+// ─── session owner ────────────────────────────────────────────────────────────
+
+/// A process identified by PID and start time, so a recycled PID doesn't keep
+/// a session alive.
+struct Owner {
+    pid: u32,
+    start_time: u64,
+}
+
+impl Owner {
+    fn of(pid: u32) -> Option<Self> {
+        Some(Self { pid, start_time: start_time(pid)? })
+    }
+
+    fn alive(&self) -> bool {
+        start_time(self.pid) == Some(self.start_time)
+    }
+}
+
+/// Field 22 of `/proc/<pid>/stat`: start time in clock ticks since boot.
+#[cfg(target_os = "linux")]
+fn start_time(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // `comm` (field 2) is parenthesized and may contain spaces or parens.
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Start time in microseconds since the epoch, from `proc_pidinfo`.
+#[cfg(target_os = "macos")]
+fn start_time(pid: u32) -> Option<u64> {
+    // SAFETY: `proc_bsdinfo` is plain data; all zeroes is a valid value.
+    let mut info: libc::proc_bsdinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_bsdinfo>() as libc::c_int;
+    // SAFETY: `info` is valid for writes of `size` bytes.
+    let n = unsafe {
+        libc::proc_pidinfo(
+            pid as libc::c_int,
+            libc::PROC_PIDTBSDINFO,
+            0,
+            (&mut info as *mut libc::proc_bsdinfo).cast(),
+            size,
+        )
+    };
+    (n == size).then(|| info.pbi_start_tvsec * 1_000_000 + info.pbi_start_tvusec)
+}
@@ -0,0 +1,222 @@
+//! Opt-in hardening (`[sandbox] enabled = true`), applied once startup is
+//! done reading keys and config and before any thread starts, so every
+//! thread and every child (`wl-copy`, `wl-paste --watch`, the `clipto` it
+//! runs) inherits it:
+//!
+//! - Landlock limits the filesystem to the runtime directory, the socket's
+//!   directory, the log and stats directories, the temp directory (`wl-copy`
+//!   buffers there), read-only system directories and `/proc`, and executing
+//!   the backend's programs and their interpreters. `$HOME` is out of reach.
+//! - seccomp refuses every socket family but `AF_UNIX`, so nothing can be
+//!   sent over the network, and a handful of syscalls nothing here needs
+//!   (`ptrace`, `bpf`, `io_uring_setup`, …).
+//!
+//! Connecting to Unix sockets (Wayland, D-Bus, journald) isn't a filesystem
+//! access to Landlock and keeps working.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clipto_ipc::Endpoint;
+use landlock::{
+    Access, AccessFs, BitFlags, PathBeneath, PathFd, Ruleset, RulesetAttr, RulesetCreatedAttr,
+    RulesetStatus, ABI,
+};
+use seccompiler::{
+    BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+    SeccompRule,
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::x11;
+
+/// `[sandbox]` config table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    pub enabled: bool,
+    /// More paths to allow reading, e.g. where a non-FHS system keeps the
+    /// libraries the helpers load.
+    pub allow_read: Vec<PathBuf>,
+    /// More programs to allow running, e.g. the interpreter of a wrapper
+    /// script that is found through `env`.
+    pub allow_exec: Vec<PathBuf>,
+}
+
+/// Read-only for everyone: libraries, `ld.so.cache`, locales, `/proc` for
+/// peer lookups.
+const SYSTEM_READ: &[&str] = &["/usr", "/lib", "/lib64", "/etc", "/nix/store", "/proc"];
+
+/// Never needed by clipd or the helpers it runs.
+const DENIED_SYSCALLS: &[i64] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_io_uring_setup, // io_uring can open sockets past the filter
+    libc::SYS_open_by_handle_at,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_keyctl,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_reboot,
+];
+
+/// Restrict this process for good. `programs` are what the backend and the
+/// compositor queries run, by name or path.
+pub fn apply(config: &Config, programs: &[PathBuf]) -> Result<()> {
+    landlock(config, programs).context("Landlock")?;
+    seccomp().context("seccomp")?;
+    info!("sandbox applied");
+    Ok(())
+}
+
+fn landlock(config: &Config, programs: &[PathBuf]) -> Result<()> {
+    let abi = ABI::V5;
+
+    let mut writable = vec![std::env::temp_dir()];
+    writable.extend(clipto_ipc::runtime_dir().ok());
+    if let Ok(Endpoint::Path(socket)) = clipto_ipc::socket() {
+        writable.extend(socket.parent().map(Path::to_owned));
+    }
+    for file in [&config.log_file, &config.stats_textfile].into_iter().flatten() {
+        writable.extend(file.parent().map(Path::to_owned));
+    }
+
+    let mut readable: Vec<PathBuf> = SYSTEM_READ.iter().map(PathBuf::from).collect();
+    readable.extend(config.sandbox.allow_read.iter().cloned());
+    // The `clipto` that `wl-paste --watch` runs reads it for `socket`.
+    readable.extend(clipto_ipc::config_path());
+    if config.x11.sync {
+        readable.extend(x11::authority());
+    }
+
+    let mut executable: Vec<PathBuf> = programs.iter().filter_map(|p| which(p)).collect();
+    executable.extend(config.sandbox.allow_exec.iter().cloned());
+    let mut i = 0;
+    while i < executable.len() {
+        if let Some(interp) = interpreter(&executable[i]) {
+            if !executable.contains(&interp) {
+                executable.push(interp);
+            }
+        }
+        i += 1;
+    }
+
+    let file = |access: BitFlags<AccessFs>| access & AccessFs::from_file(abi);
+    let mut rules = Vec::new();
+    for dir in &writable {
+        rules.push((dir, AccessFs::from_all(abi)));
+    }
+    for path in &readable {
+        let access = AccessFs::ReadFile | AccessFs::ReadDir;
+        rules.push((path, if path.is_dir() { access } else { file(access) }));
+    }
+    for path in &executable {
+        rules.push((path, AccessFs::ReadFile | AccessFs::Execute));
+    }
+    let dev_null = PathBuf::from("/dev/null");
+    rules.push((&dev_null, AccessFs::ReadFile | AccessFs::WriteFile));
+
+    let mut ruleset = Ruleset::default().handle_access(AccessFs::from_all(abi))?.create()?;
+    for (path, access) in rules {
+        // Missing paths (no `/nix/store`, a log directory not created yet)
+        // are simply not granted.
+        let Ok(fd) = PathFd::new(path) else { continue };
+        ruleset = ruleset.add_rule(PathBeneath::new(fd, access))?;
+    }
+
+    let status = ruleset.restrict_self()?;
+    match status.ruleset {
+        RulesetStatus::FullyEnforced => {}
+        RulesetStatus::PartiallyEnforced => {
+            warn!("this kernel's Landlock enforces only part of the filesystem rules")
+        }
+        RulesetStatus::NotEnforced => bail!("not supported by this kernel"),
+    }
+    Ok(())
+}
+
+fn seccomp() -> Result<()> {
+    let mut rules: BTreeMap<i64, Vec<SeccompRule>> =
+        DENIED_SYSCALLS.iter().map(|&nr| (nr, Vec::new())).collect();
+    let not_unix = SeccompCondition::new(
+        0,
+        SeccompCmpArgLen::Dword,
+        SeccompCmpOp::Ne,
+        libc::AF_UNIX as u64,
+    )?;
+    rules.insert(libc::SYS_socket, vec![SeccompRule::new(vec![not_unix])?]);
+
+    let filter = SeccompFilter::new(
+        rules,
+        SeccompAction::Allow,
+        SeccompAction::Errno(libc::EPERM as u32),
+        std::env::consts::ARCH.try_into()?,
+    )?;
+    let program: BpfProgram = filter.try_into()?;
+    seccompiler::apply_filter_all_threads(&program)?;
+    Ok(())
+}
+
+/// `program` as exec would find it through `$PATH`.
+fn which(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return Some(program.to_owned());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// The interpreter exec also opens to run `path`: the `#!` line of a script,
+/// or `PT_INTERP` (the dynamic loader) of a 64-bit little-endian ELF.
+fn interpreter(path: &Path) -> Option<PathBuf> {
+    let mut head = vec![0; 4096];
+    let n = File::open(path).ok()?.read(&mut head).ok()?;
+    let head = &head[..n];
+
+    if let Some(line) = head.strip_prefix(b"#!") {
+        let line = line.split(|&b| b == b'\n').next()?;
+        let interp = std::str::from_utf8(line).ok()?.split_whitespace().next()?;
+        return Some(PathBuf::from(interp));
+    }
+
+    if !head.starts_with(b"\x7fELF\x02\x01") {
+        return None;
+    }
+    let field = |at: usize, len: usize| -> Option<usize> {
+        let bytes = head.get(at..at + len)?;
+        Some(bytes.iter().rev().fold(0, |n, &b| n << 8 | b as usize))
+    };
+    let (phoff, phentsize, phnum) = (field(32, 8)?, field(54, 2)?, field(56, 2)?);
+    for i in 0..phnum {
+        let ph = phoff + i * phentsize;
+        if field(ph, 4)? == 3 {
+            // PT_INTERP: a NUL-terminated path at p_offset, p_filesz long.
+            let (offset, size) = (field(ph + 8, 8)?, field(ph + 32, 8)?);
+            let interp = head.get(offset..offset + size)?;
+            let interp = interp.strip_suffix(b"\0").unwrap_or(interp);
+            return Some(PathBuf::from(std::str::from_utf8(interp).ok()?));
+        }
+    }
+    None
+}
@@ -0,0 +1,96 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tracing::warn;
+
+use clipto_ipc::Stats;
+
+/// Lock-free daemon counters. Shared by every connection thread.
+pub struct Metrics {
+    started: Instant,
+    pub copies: AtomicU64,
+    pub pastes: AtomicU64,
+    pub bytes_stored: AtomicU64,
+    pub wayland_syncs: AtomicU64,
+    pub errors: AtomicU64,
+    pub rejected_payloads: AtomicU64,
+    pub throttled: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            copies: AtomicU64::new(0),
+            pastes: AtomicU64::new(0),
+            bytes_stored: AtomicU64::new(0),
+            wayland_syncs: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            rejected_payloads: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+        }
+    }
+
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Stats {
+        Stats {
+            uptime_secs: self.started.elapsed().as_secs(),
+            copies: self.copies.load(Ordering::Relaxed),
+            pastes: self.pastes.load(Ordering::Relaxed),
+            bytes_stored: self.bytes_stored.load(Ordering::Relaxed),
+            wayland_syncs: self.wayland_syncs.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            rejected_payloads: self.rejected_payloads.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Render a snapshot in the Prometheus text exposition format.
+fn render_prometheus(stats: &Stats) -> String {
+    let metrics: [(&str, &str, &str, u64); 8] = [
+        ("clipd_uptime_seconds", "gauge", "Seconds since clipd started.", stats.uptime_secs),
+        ("clipd_copies_total", "counter", "Successful copies from any source.", stats.copies),
+        ("clipd_pastes_total", "counter", "Successful pastes.", stats.pastes),
+        ("clipd_bytes_stored", "gauge", "Plaintext size of the current entry.", stats.bytes_stored),
+        ("clipd_wayland_syncs_total", "counter", "Payloads forwarded to the compositor.", stats.wayland_syncs),
+        ("clipd_errors_total", "counter", "Connection, encryption, and sync failures.", stats.errors),
+        ("clipd_rejected_payloads_total", "counter", "Copy requests refused by the daemon.", stats.rejected_payloads),
+        ("clipd_throttled_total", "counter", "Requests refused by rate limiting.", stats.throttled),
+    ];
+
+    let mut out = String::new();
+    for (name, kind, help, value) in metrics {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        let _ = writeln!(out, "{name} {value}");
+    }
+    out
+}
+
+/// Write the textfile atomically so node_exporter never reads a partial file.
+fn write_textfile(path: &Path, stats: &Stats) -> Result<()> {
+    let tmp = path.with_extension("prom.tmp");
+    std::fs::write(&tmp, render_prometheus(stats))
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("failed to rename {} into place", tmp.display()))?;
+    Ok(())
+}
+
+/// Spawn a thread that rewrites the node_exporter textfile every `interval`.
+pub fn start_textfile_writer(metrics: Arc<Metrics>, path: PathBuf, interval: Duration) {
+    std::thread::spawn(move || loop {
+        if let Err(e) = write_textfile(&path, &metrics.snapshot()) {
+            warn!("stats textfile: {e:#}");
+        }
+        std::thread::sleep(interval);
+    });
+}
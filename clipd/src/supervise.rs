@@ -0,0 +1,165 @@
+//! Keeps a backend's watcher child running: restarts it with backoff when it
+//! exits, and (on Linux) has the kernel kill it when clipd dies, however it
+//! dies, via `PR_SET_PDEATHSIG`.
+
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+use clipto_ipc::WatcherState;
+use tracing::{debug, info, warn};
+
+use crate::unix_secs;
+
+/// First restart delay, doubled after each quick exit.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A child that ran this long is considered healthy; the next exit restarts
+/// it after `MIN_BACKOFF` again.
+const STABLE_AFTER: Duration = Duration::from_secs(30);
+
+/// A running supervisor. Dropping it detaches the thread without stopping
+/// the child; call `stop` to end both.
+pub struct Supervisor {
+    stop: Arc<AtomicBool>,
+    /// The child's PID while it is unreaped, so `stop` never signals a
+    /// recycled PID.
+    pid: Arc<Mutex<Option<u32>>>,
+    thread: JoinHandle<()>,
+}
+
+impl Supervisor {
+    /// Start `command()` and keep it running. `run` gets each child once it
+    /// has started and returns when it is done with it (the child exited,
+    /// closed its output, or `run` gave up); the child is then killed if still
+    /// alive, reaped, and restarted. `state` is kept current for
+    /// `clipto status`.
+    pub fn start(
+        name: &'static str,
+        state: Arc<Mutex<WatcherState>>,
+        command: impl Fn() -> Command + Send + 'static,
+        run: impl Fn(&mut Child) -> Result<()> + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let pid = Arc::new(Mutex::new(None));
+        let thread = {
+            let (stop, pid) = (Arc::clone(&stop), Arc::clone(&pid));
+            std::thread::spawn(move || supervise(name, &state, &stop, &pid, command, run))
+        };
+        Self { stop, pid, thread }
+    }
+
+    /// Kill the child and wait for the supervisor to finish.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(pid) = *self.pid.lock().unwrap() {
+            // SAFETY: plain syscall; `pid` is our unreaped child.
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+        }
+        self.thread.thread().unpark();
+        let _ = self.thread.join();
+    }
+}
+
+fn supervise(
+    name: &str,
+    state: &Mutex<WatcherState>,
+    stop: &AtomicBool,
+    pid: &Mutex<Option<u32>>,
+    command: impl Fn() -> Command,
+    run: impl Fn(&mut Child) -> Result<()>,
+) {
+    let mut restarts = 0;
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        // Under the PID lock, so a concurrent `stop` either prevents the
+        // spawn or sees the PID to kill.
+        let mut guard = pid.lock().unwrap();
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let mut cmd = command();
+        die_with_parent(&mut cmd);
+        let spawned = cmd.spawn();
+        if let Ok(child) = &spawned {
+            *guard = Some(child.id());
+        }
+        drop(guard);
+
+        let started = Instant::now();
+        let last_exit = match spawned {
+            Ok(mut child) => {
+                info!(pid = child.id(), "{name} started");
+                *state.lock().unwrap() = WatcherState::Running {
+                    pid: child.id(),
+                    since_unix: unix_secs(SystemTime::now()),
+                    restarts,
+                };
+                let result = run(&mut child);
+                *pid.lock().unwrap() = None;
+                let _ = child.kill();
+                let status = child.wait();
+
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                match (result, status) {
+                    (Err(e), _) => format!("{e:#}"),
+                    (Ok(()), Ok(status)) => format!("exited with {status}"),
+                    (Ok(()), Err(e)) => format!("wait failed: {e}"),
+                }
+            }
+            Err(e) => format!("failed to start: {e}"),
+        };
+
+        if started.elapsed() >= STABLE_AFTER {
+            backoff = MIN_BACKOFF;
+        }
+        restarts += 1;
+        warn!("{name} {last_exit}; restarting in {}s", backoff.as_secs());
+        *state.lock().unwrap() = WatcherState::Restarting {
+            restarts,
+            last_exit,
+            retry_unix: unix_secs(SystemTime::now() + backoff),
+        };
+
+        // `stop` unparks us; spurious wakeups just restart a little early.
+        std::thread::park_timeout(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    debug!("{name} supervisor stopped");
+    *state.lock().unwrap() = WatcherState::Stopped;
+}
+
+/// Have the kernel send the child SIGTERM when the thread that spawned it
+/// (the supervisor, which outlives it) or the whole daemon dies, SIGKILL
+/// included.
+#[cfg(target_os = "linux")]
+fn die_with_parent(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    let parent = std::process::id();
+    // SAFETY: only async-signal-safe calls between fork and exec.
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // The parent may have died before prctl took effect.
+            if libc::getppid() as u32 != parent {
+                return Err(std::io::Error::other("parent exited"));
+            }
+            Ok(())
+        });
+    }
+}
+
+/// No portable equivalent; the child notices clipd is gone when its output
+/// pipe breaks.
+#[cfg(not(target_os = "linux"))]
+fn die_with_parent(_cmd: &mut Command) {}
@@ -0,0 +1,417 @@
+//! The Wayland backend: `wl-copy` to push, `wl-paste --watch` to follow the
+//! compositor clipboard.
+//!
+//! The compositor is found by its socket in `$XDG_RUNTIME_DIR`, not by the
+//! `WAYLAND_DISPLAY` clipd happened to start with: a daemon started before
+//! graphical login, or outliving a session whose successor got another
+//! display name, attaches to whichever `wayland-*` socket exists now.
+
+use std::io::Write;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use clipto_ipc::{Status, WatcherState};
+use tracing::{debug, error, info, info_span, warn};
+
+use crate::backend::Backend;
+use crate::config::WatchConfig;
+use crate::supervise::Supervisor;
+use crate::x11::{self, X11Config, X11};
+use crate::Daemon;
+
+pub struct Wayland {
+    clipto_bin: PathBuf,
+    /// The display synced with (`wayland-1`), kept current by the watcher.
+    display: Arc<Mutex<Option<String>>>,
+    state: Arc<Mutex<WatcherState>>,
+    /// `None` without a runtime directory to watch.
+    watcher: Option<Watcher>,
+    /// Where user copies also go when `[x11] sync` is on.
+    x11: Option<X11>,
+}
+
+impl Wayland {
+    pub fn new(clipto_bin: PathBuf, watch: WatchConfig, x11: &X11Config) -> Self {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+        let display = runtime_dir.as_deref().and_then(|dir| pick_display(dir, None));
+        let display = Arc::new(Mutex::new(display));
+        let state = Arc::new(Mutex::new(WatcherState::Stopped));
+        let watcher = runtime_dir.map(|runtime_dir| Watcher {
+            runtime_dir,
+            display: Arc::clone(&display),
+            state: Arc::clone(&state),
+            clipto_bin: clipto_bin.clone(),
+            watch,
+            attached: Arc::default(),
+        });
+        Self { clipto_bin, display, state, watcher, x11: X11::new(x11) }
+    }
+
+    /// `wl-copy` (or `systemd-run … wl-copy`) aimed at the current display, or
+    /// `None` with no compositor — TTY sessions are expected to hit this path.
+    fn command(&self, program: &str) -> Option<Command> {
+        let display = self.display.lock().unwrap().clone()?;
+        let mut cmd = Command::new(program);
+        cmd.env("WAYLAND_DISPLAY", display);
+        Some(cmd)
+    }
+}
+
+/// Whether `name` in the runtime directory could be a compositor socket.
+fn is_display_name(name: &str) -> bool {
+    (name.starts_with("wayland-") && !name.ends_with(".lock"))
+        || std::env::var("WAYLAND_DISPLAY").is_ok_and(|d| d == name)
+}
+
+fn is_socket(path: &Path) -> bool {
+    path.metadata().is_ok_and(|m| m.file_type().is_socket())
+}
+
+/// The display to sync with: `current` while its socket exists, else the
+/// startup `WAYLAND_DISPLAY` if its socket exists, else the first `wayland-*`
+/// socket by name.
+fn pick_display(runtime_dir: &Path, current: Option<&str>) -> Option<String> {
+    if let Some(current) = current.filter(|d| is_socket(&runtime_dir.join(d))) {
+        return Some(current.to_owned());
+    }
+    let env = std::env::var("WAYLAND_DISPLAY").ok();
+    if let Some(env) = env.filter(|d| is_socket(&runtime_dir.join(d))) {
+        return Some(env);
+    }
+    let mut names: Vec<String> = std::fs::read_dir(runtime_dir)
+        .ok()?
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .filter(|name| is_display_name(name) && is_socket(&runtime_dir.join(name)))
+        .collect();
+    names.sort();
+    names.into_iter().next()
+}
+
+impl Backend for Wayland {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    fn set(&self, payload: &[u8], mime: Option<&str>) -> Result<bool> {
+        let Some(mut cmd) = self.command("wl-copy") else {
+            return Ok(false);
+        };
+        if let Some(mime) = wl_copy_type(mime) {
+            cmd.args(["--type", mime]);
+        }
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("failed to spawn wl-copy")?;
+
+        if let Some(stdin) = child.stdin.as_mut() {
+            stdin.write_all(payload).context("failed to write to wl-copy")?;
+        }
+
+        child.wait().context("wl-copy failed")?;
+        if let Some(x11) = &self.x11 {
+            if let Err(e) = x11.set(payload, mime) {
+                warn!("X11 sync failed: {e:#}");
+            }
+        }
+        Ok(true)
+    }
+
+    /// A `wl-copy --paste-once` kept in the foreground, so its exit says the
+    /// offer is over. `wl-paste --watch` reads every new selection, which
+    /// would take the one paste, so it is stopped until then.
+    fn set_once(
+        &self,
+        payload: &[u8],
+        mime: Option<&str>,
+        done: Box<dyn FnOnce() + Send>,
+    ) -> Result<bool> {
+        let (Some(mut cmd), Some(watcher)) = (self.command("wl-copy"), self.watcher.clone()) else {
+            return Ok(false);
+        };
+        cmd.args(["--foreground", "--paste-once"]);
+        if let Some(mime) = wl_copy_type(mime) {
+            cmd.args(["--type", mime]);
+        }
+
+        let offer = watcher.pause();
+        let mut child = match cmd.stdin(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                watcher.resume(offer);
+                return Err(e).context("failed to spawn wl-copy");
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(e) = stdin.write_all(payload) {
+                let _ = child.kill();
+                let _ = child.wait();
+                watcher.resume(offer);
+                return Err(e).context("failed to write to wl-copy");
+            }
+        }
+
+        std::thread::spawn(move || {
+            let _ = child.wait();
+            debug!("paste-once offer ended");
+            watcher.resume(offer);
+            done();
+        });
+        Ok(true)
+    }
+
+    fn clear(&self) -> Result<()> {
+        let Some(mut cmd) = self.command("wl-copy") else {
+            return Ok(());
+        };
+        let status = cmd
+            .arg("--clear")
+            .status()
+            .context("failed to spawn wl-copy")?;
+        if !status.success() {
+            bail!("wl-copy --clear exited with {status}");
+        }
+        if let Some(x11) = &self.x11 {
+            x11.clear().context("failed to clear the X11 clipboard")?;
+        }
+        Ok(())
+    }
+
+    /// Hand the entry to a `wl-copy` that outlives the daemon, so stopping
+    /// clipd doesn't empty the GUI clipboard. Under systemd the unit's cgroup
+    /// is killed on stop, taking the regular sync's `wl-copy` with it, so the
+    /// copy is launched in its own transient scope via `systemd-run`.
+    fn persist(&self, payload: &[u8], mime: Option<&str>) -> Result<()> {
+        let under_systemd = std::env::var_os("INVOCATION_ID").is_some();
+        let Some(mut cmd) = self.command(if under_systemd { "systemd-run" } else { "wl-copy" })
+        else {
+            return Ok(());
+        };
+        if under_systemd {
+            cmd.args(["--user", "--scope", "--quiet", "--collect", "--", "wl-copy"]);
+        }
+
+        if let Some(mime) = wl_copy_type(mime) {
+            cmd.args(["--type", mime]);
+        }
+
+        // Own process group: a Ctrl-C aimed at a foreground clipd must not reach it.
+        let mut child = cmd
+            .process_group(0)
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("failed to spawn wl-copy")?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(payload).context("failed to write to wl-copy")?;
+        }
+
+        // wl-copy forks to serve the selection; this only waits for the parent.
+        child.wait().context("wl-copy failed")?;
+        info!(bytes = payload.len(), "clipboard handed to a detached wl-copy");
+        Ok(())
+    }
+
+    /// `wl-paste --watch` reports changes through the `clipto` binary, over
+    /// the socket, so the daemon handle isn't needed here.
+    fn start_watcher(&self, _daemon: Arc<Daemon>) {
+        if let Some(watcher) = self.watcher.clone() {
+            start_watcher(watcher);
+        }
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            backend: self.name().to_owned(),
+            display: self.display.lock().unwrap().clone(),
+            watcher: self.state.lock().unwrap().clone(),
+        }
+    }
+
+    fn programs(&self) -> Vec<PathBuf> {
+        let mut programs: Vec<PathBuf> =
+            ["wl-copy", "wl-paste", "systemd-run"].map(PathBuf::from).into();
+        programs.push(self.clipto_bin.clone());
+        if self.x11.is_some() {
+            programs.push(PathBuf::from(x11::PROGRAM));
+        }
+        programs
+    }
+}
+
+/// The `--type` to give `wl-copy` for an entry of type `mime`. Plain text is
+/// left untyped: `wl-copy` then offers it under every text alias apps ask for
+/// (`text/plain`, `UTF8_STRING`, …) instead of just the one we recorded.
+fn wl_copy_type(mime: Option<&str>) -> Option<&str> {
+    mime.filter(|m| !m.starts_with("text/plain"))
+}
+
+/// What the inotify thread needs to (re)attach to a display.
+#[derive(Clone)]
+struct Watcher {
+    runtime_dir: PathBuf,
+    display: Arc<Mutex<Option<String>>>,
+    state: Arc<Mutex<WatcherState>>,
+    clipto_bin: PathBuf,
+    watch: WatchConfig,
+    attached: Arc<Mutex<Attached>>,
+}
+
+/// The display `wl-paste --watch` runs against, shared by the inotify thread
+/// and paste-once offers.
+#[derive(Default)]
+struct Attached {
+    display: Option<String>,
+    supervisor: Option<Supervisor>,
+    /// The live paste-once offer, during which no watcher runs.
+    offer: Option<u64>,
+    offers: u64,
+}
+
+/// Spawn a thread that uses inotify to watch `$XDG_RUNTIME_DIR` for
+/// compositor sockets coming and going. Runs a supervised `wl-paste --watch`
+/// against the chosen display, and moves it to another display when that
+/// one's socket is deleted. Zero polling.
+fn start_watcher(watcher: Watcher) {
+    use inotify::{Inotify, WatchMask};
+
+    std::thread::spawn(move || {
+        let _span = info_span!("wayland_watcher").entered();
+
+        let mut inotify = match Inotify::init() {
+            Ok(i) => i,
+            Err(e) => { error!("inotify init: {e}"); return; }
+        };
+
+        let dir = &watcher.runtime_dir;
+        if let Err(e) = inotify.watches().add(dir, WatchMask::CREATE | WatchMask::DELETE) {
+            error!("inotify watch: {e}");
+            return;
+        }
+
+        // If a compositor is already up when the daemon starts, attach now.
+        watcher.attach();
+
+        let mut buf = [0u8; 1024];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buf) {
+                Ok(e) => e,
+                Err(e) => { error!("inotify read: {e}"); break; }
+            };
+
+            let relevant = events
+                .filter_map(|event| event.name)
+                .any(|name| name.to_str().is_some_and(is_display_name));
+            if relevant {
+                watcher.attach();
+            }
+        }
+    });
+}
+
+impl Watcher {
+    /// Point the backend and `wl-paste --watch` at the display
+    /// `pick_display` chooses now, restarting the watcher if that changed.
+    fn attach(&self) {
+        let mut attached = self.attached.lock().unwrap();
+        let wanted = pick_display(&self.runtime_dir, attached.display.as_deref());
+        if wanted != attached.display {
+            if let Some(old) = attached.display.take() {
+                info!(display = %old, "compositor socket removed");
+            }
+            if let Some(supervisor) = attached.supervisor.take() {
+                supervisor.stop();
+            }
+            *self.display.lock().unwrap() = wanted.clone();
+            if let Some(wanted) = &wanted {
+                info!(display = %wanted, "compositor socket found");
+            }
+            attached.display = wanted;
+        }
+        if attached.supervisor.is_some() || attached.offer.is_some() {
+            return;
+        }
+        if let Some(display) = attached.display.clone() {
+            let (clipto_bin, watch) = (self.clipto_bin.clone(), self.watch.clone());
+            attached.supervisor = Some(Supervisor::start(
+                "wl-paste --watch",
+                Arc::clone(&self.state),
+                move || wl_paste(&display, &clipto_bin, &watch),
+                wait_exit,
+            ));
+        }
+    }
+
+    /// Stop `wl-paste --watch` for a paste-once offer, returning its id.
+    fn pause(&self) -> u64 {
+        let mut attached = self.attached.lock().unwrap();
+        if let Some(supervisor) = attached.supervisor.take() {
+            supervisor.stop();
+        }
+        attached.offers += 1;
+        attached.offer = Some(attached.offers);
+        attached.offers
+    }
+
+    /// Restart `wl-paste --watch` once `offer` is over, unless a newer offer
+    /// replaced it. The watcher then reads whatever the clipboard holds now.
+    fn resume(&self, offer: u64) {
+        let mut attached = self.attached.lock().unwrap();
+        if attached.offer != Some(offer) {
+            return;
+        }
+        attached.offer = None;
+        drop(attached);
+        self.attach();
+    }
+}
+
+/// Each change runs `clipto wayland-sync`, which lists the offered types and
+/// fetches the preferred one itself, so the entry's type is known; the
+/// content `wl-paste` hands it on stdin is ignored. Only with both `[watch]`
+/// lists emptied does `wl-paste` choose, via plain `clipto copy`.
+fn wl_paste(display: &str, clipto_bin: &PathBuf, watch: &WatchConfig) -> Command {
+    let mut cmd = Command::new("wl-paste");
+    cmd.env("WAYLAND_DISPLAY", display);
+    cmd.args(["--watch", "--"]).arg(clipto_bin);
+    if watch.prefer.is_empty() && watch.deny.is_empty() {
+        cmd.args(["copy", "--source", "wayland"]);
+    } else {
+        cmd.arg("wayland-sync");
+        for t in &watch.prefer {
+            cmd.arg("--prefer").arg(t);
+        }
+        for t in &watch.deny {
+            cmd.arg("--deny").arg(t);
+        }
+    }
+    cmd
+}
+
+/// Block until `child` exits, leaving it unreaped for the supervisor.
+fn wait_exit(child: &mut Child) -> Result<()> {
+    // SAFETY: `siginfo_t` is plain data; all zeroes is a valid value.
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: `info` is valid for writes.
+        let rc = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                child.id() as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if rc == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err).context("waitid failed");
+        }
+    }
+}
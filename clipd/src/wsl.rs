@@ -0,0 +1,178 @@
+//! The WSL backend: the Windows clipboard, through the interop executables.
+//! `clip.exe` sets it; a long-running `powershell.exe` polls `Get-Clipboard`
+//! and reports each change, the way `wl-paste --watch` does under Wayland.
+//!
+//! Only text crosses over. Line endings are converted both ways, so Windows
+//! apps see CRLF and tmux and the shell see LF.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use clipto_ipc::{CopySource, Request, Response, Status, WatcherState};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::backend::Backend;
+use crate::supervise::Supervisor;
+use crate::{dispatch, Daemon};
+
+/// `[wsl]` config table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WslConfig {
+    /// How often the watcher reads the Windows clipboard, in milliseconds.
+    pub poll_interval_ms: u64,
+}
+
+impl Default for WslConfig {
+    fn default() -> Self {
+        Self { poll_interval_ms: 500 }
+    }
+}
+
+/// Windows has no change notification reachable from PowerShell without a
+/// message loop, so this polls. Output is one base64 line of UTF-8 per change.
+const WATCH_SCRIPT: &str = r#"
+$last = $null
+while ($true) {
+    try { $text = Get-Clipboard -Raw } catch { $text = $last }
+    if ($text -ne $last) {
+        $last = $text
+        if ($text) {
+            [Console]::Out.WriteLine([Convert]::ToBase64String([Text.Encoding]::UTF8.GetBytes($text)))
+            [Console]::Out.Flush()
+        }
+    }
+    Start-Sleep -Milliseconds @POLL@
+}
+"#;
+
+/// Whether this is a WSL kernel (`…-microsoft-standard-WSL2`).
+pub fn detected() -> bool {
+    std::fs::read_to_string("/proc/version")
+        .is_ok_and(|v| v.to_ascii_lowercase().contains("microsoft"))
+}
+
+pub struct Wsl {
+    config: WslConfig,
+    state: Arc<Mutex<WatcherState>>,
+}
+
+impl Wsl {
+    pub fn new(config: WslConfig) -> Self {
+        Self { config, state: Arc::new(Mutex::new(WatcherState::Stopped)) }
+    }
+}
+
+impl Backend for Wsl {
+    fn name(&self) -> &'static str {
+        "windows"
+    }
+
+    fn set(&self, payload: &[u8], mime: Option<&str>) -> Result<bool> {
+        if mime.is_some_and(|m| !m.starts_with("text/")) {
+            return Ok(false);
+        }
+        let Ok(text) = std::str::from_utf8(payload) else {
+            return Ok(false);
+        };
+        clip_exe(&utf16_crlf(text))?;
+        Ok(true)
+    }
+
+    fn clear(&self) -> Result<()> {
+        // Empty input leaves the clipboard empty, like `type nul | clip`.
+        clip_exe(&[])
+    }
+
+    /// The Windows clipboard keeps its content after we exit.
+    fn persist(&self, _payload: &[u8], _mime: Option<&str>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Runs for the daemon's lifetime: the supervisor is never stopped.
+    fn start_watcher(&self, daemon: Arc<Daemon>) {
+        let script = WATCH_SCRIPT.replace("@POLL@", &self.config.poll_interval_ms.to_string());
+        Supervisor::start(
+            "powershell.exe clipboard watcher",
+            Arc::clone(&self.state),
+            move || {
+                let mut cmd = Command::new("powershell.exe");
+                cmd.args(["-NoProfile", "-NonInteractive", "-Command", &script])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped());
+                cmd
+            },
+            move |child| watch(&daemon, child),
+        );
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            backend: self.name().to_owned(),
+            display: None,
+            watcher: self.state.lock().unwrap().clone(),
+        }
+    }
+
+    fn programs(&self) -> Vec<PathBuf> {
+        ["clip.exe", "powershell.exe"].map(PathBuf::from).into()
+    }
+}
+
+/// Store what a `powershell.exe` watcher reports until its output ends.
+fn watch(daemon: &Arc<Daemon>, child: &mut Child) -> Result<()> {
+    let stdout = child.stdout.take().context("no stdout")?;
+    for line in BufReader::new(stdout).lines() {
+        let line = line.context("failed to read from powershell.exe")?;
+        let bytes = match base64::engine::general_purpose::STANDARD.decode(line.trim()) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                debug!("ignoring watcher output: {e}");
+                continue;
+            }
+        };
+        let payload = String::from_utf8_lossy(&bytes).replace("\r\n", "\n").into_bytes();
+        let request = Request::Copy {
+            payload,
+            source: CopySource::Wayland,
+            mime: Some("text/plain;charset=utf-8".to_owned()),
+            once: false,
+        };
+        if let Response::Error { message } = dispatch(daemon, request, "windows") {
+            warn!("windows clipboard change not stored: {message}");
+        }
+    }
+    Ok(())
+}
+
+/// Feed `input` to `clip.exe`, which sets the Windows clipboard.
+fn clip_exe(input: &[u8]) -> Result<()> {
+    let mut child = Command::new("clip.exe")
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("failed to spawn clip.exe")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).context("failed to write to clip.exe")?;
+    }
+    let status = child.wait().context("clip.exe failed")?;
+    if !status.success() {
+        bail!("clip.exe exited with {status}");
+    }
+    Ok(())
+}
+
+/// `text` with CRLF line endings, as UTF-16LE behind a byte order mark:
+/// `clip.exe` reads anything else in the console code page, mangling
+/// non-ASCII text.
+fn utf16_crlf(text: &str) -> Vec<u8> {
+    let text = text.replace("\r\n", "\n").replace('\n', "\r\n");
+    std::iter::once(0xfeff)
+        .chain(text.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
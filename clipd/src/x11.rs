@@ -0,0 +1,89 @@
+//! Optional push of user copies to the X11 CLIPBOARD selection, alongside
+//! `wl-copy`, for X11 apps under XWayland that don't see the compositor's
+//! own bridging reliably.
+//!
+//! Only pushed, never watched: what X11 apps copy still reaches clipd
+//! through the compositor. If the compositor bridges our X11 copy back to
+//! the Wayland clipboard, `wl-paste --watch` reports the payload just synced
+//! and the echo filter drops it like any other echo.
+
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+pub const PROGRAM: &str = "xclip";
+
+/// `[x11]` config table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct X11Config {
+    /// Also hand user copies to `xclip` for the CLIPBOARD selection.
+    pub sync: bool,
+    /// X display to push to; `$DISPLAY` by default.
+    pub display: Option<String>,
+}
+
+pub struct X11 {
+    display: String,
+}
+
+impl X11 {
+    /// `None` unless `config.sync` is set and there is a display to use.
+    pub fn new(config: &X11Config) -> Option<Self> {
+        if !config.sync {
+            return None;
+        }
+        let display = config.display.clone().or_else(|| std::env::var("DISPLAY").ok());
+        display.filter(|d| !d.is_empty()).map(|display| Self { display })
+    }
+
+    /// Make `payload` the CLIPBOARD selection. `xclip` forks to serve it
+    /// until another client takes the selection; this waits for the parent.
+    pub fn set(&self, payload: &[u8], mime: Option<&str>) -> Result<()> {
+        let mut cmd = self.xclip();
+        // Plain text untyped, as for wl-copy: xclip then offers the usual
+        // text targets (`UTF8_STRING`, `STRING`, …).
+        if let Some(mime) = mime.filter(|m| !m.starts_with("text/plain")) {
+            cmd.args(["-t", mime]);
+        }
+        let mut child = cmd.stdin(Stdio::piped()).spawn().context("failed to spawn xclip")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(payload).context("failed to write to xclip")?;
+        }
+        let status = child.wait().context("xclip failed")?;
+        if !status.success() {
+            bail!("xclip exited with {status}");
+        }
+        Ok(())
+    }
+
+    /// X11 has no empty selection to set, so this takes it with an empty
+    /// string, which ends the `xclip` serving the last copy.
+    pub fn clear(&self) -> Result<()> {
+        self.set(b"", None)
+    }
+
+    fn xclip(&self) -> Command {
+        let mut cmd = Command::new(PROGRAM);
+        cmd.args(["-selection", "clipboard", "-i"])
+            .env("DISPLAY", &self.display)
+            .stdout(Stdio::null())
+            // Own process group, like the persisted wl-copy: a Ctrl-C aimed
+            // at a foreground clipd must not end the selection.
+            .process_group(0);
+        cmd
+    }
+}
+
+/// The X authority file `xclip` reads to connect: `$XAUTHORITY`, else
+/// `~/.Xauthority`.
+pub fn authority() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("XAUTHORITY") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".Xauthority"))
+}
@@ -0,0 +1,204 @@
+//! A typed client for clipd, for editors, status bars and other tools that
+//! would otherwise shell out to `clipto` or speak the bincode protocol
+//! themselves.
+//!
+//! ```no_run
+//! use clipto_client::{Client, CopyOptions};
+//!
+//! let client = Client::new()?;
+//! client.copy(b"hello".to_vec(), CopyOptions::default())?;
+//! assert_eq!(client.paste()?, b"hello");
+//!
+//! for change in client.subscribe()? {
+//!     change?;
+//!     println!("clipboard changed");
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::io::{self, ErrorKind};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+pub use clipto_ipc::{CopySource, Endpoint, Request, Response};
+
+/// Run when nothing listens on the socket, e.g. to start the daemon. The
+/// connection is retried once it returns `Ok`.
+pub type Starter = fn(&Endpoint) -> Result<()>;
+
+/// A handle on one daemon. Each request opens its own connection, so a
+/// `Client` is cheap to keep around and can be shared between threads.
+#[derive(Debug, Clone)]
+pub struct Client {
+    endpoint: Endpoint,
+    starter: Option<Starter>,
+}
+
+/// How `Client::copy` stores its payload.
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    /// Content type to offer GUI apps, e.g. `image/png`.
+    pub mime: Option<String>,
+    /// Destroy the entry after its first paste.
+    pub once: bool,
+    /// `Wayland` only for what came from the desktop clipboard, which the
+    /// daemon then doesn't sync back.
+    pub source: CopySource,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self { mime: None, once: false, source: CopySource::User }
+    }
+}
+
+impl Client {
+    /// The daemon at the configured socket: `$CLIPTO_SOCKET`, else the
+    /// config file's `socket` key, else `clipto.sock` in the runtime
+    /// directory.
+    pub fn new() -> Result<Self> {
+        Ok(Self::at(clipto_ipc::socket()?))
+    }
+
+    pub fn at(endpoint: Endpoint) -> Self {
+        Self { endpoint, starter: None }
+    }
+
+    /// Call `starter` when no daemon is listening, then connect again.
+    pub fn with_starter(mut self, starter: Starter) -> Self {
+        self.starter = Some(starter);
+        self
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    fn connect(&self) -> Result<UnixStream> {
+        let stream = match (self.endpoint.connect(), self.starter) {
+            (Err(e), Some(start)) if not_running(&e) => {
+                start(&self.endpoint)?;
+                self.endpoint.connect()
+            }
+            (result, _) => result,
+        };
+        stream.with_context(|| {
+            format!(
+                "failed to connect to clipd at {} — is it running? (`clipto daemon`)",
+                self.endpoint
+            )
+        })
+    }
+
+    /// Send one request and read its response. A throttled request is sent
+    /// again once the daemon says it may be, so a client over its rate limit
+    /// just slows down.
+    pub fn request(&self, request: &Request) -> Result<Response> {
+        loop {
+            let mut stream = self.connect()?;
+            match exchange(&mut stream, request)? {
+                Response::Throttled { retry_after_ms } => {
+                    std::thread::sleep(Duration::from_millis(retry_after_ms.max(1)));
+                }
+                response => return Ok(response),
+            }
+        }
+    }
+
+    /// Make `payload` the current clipboard.
+    pub fn copy(&self, payload: Vec<u8>, options: CopyOptions) -> Result<()> {
+        let CopyOptions { mime, once, source } = options;
+        match self.request(&Request::Copy { payload, source, mime, once })? {
+            Response::Ok => Ok(()),
+            Response::Error { message } => bail!("clipd: {message}"),
+            _ => bail!("clipd: unexpected response to Copy"),
+        }
+    }
+
+    /// The current clipboard. Fails when it is empty.
+    pub fn paste(&self) -> Result<Vec<u8>> {
+        match self.request(&Request::Paste)? {
+            Response::Payload { data } => Ok(data),
+            Response::Error { message } => bail!("clipd: {message}"),
+            _ => bail!("clipd: unexpected response to Paste"),
+        }
+    }
+
+    /// Follow clipboard changes: the iterator yields once per change (a
+    /// copy, promotion, pin, clear or wipe) and ends when the daemon exits.
+    /// Fetch what changed with `paste` or `Request::History`.
+    pub fn subscribe(&self) -> Result<Subscription> {
+        let stream = loop {
+            let mut stream = self.connect()?;
+            match exchange(&mut stream, &Request::Subscribe)? {
+                Response::Ok => break stream,
+                Response::Throttled { retry_after_ms } => {
+                    std::thread::sleep(Duration::from_millis(retry_after_ms.max(1)));
+                }
+                Response::Error { message } => bail!("clipd: {message}"),
+                _ => bail!("clipd: unexpected response to Subscribe"),
+            }
+        };
+        Ok(Subscription { stream })
+    }
+}
+
+/// Write `request` and read the response. The daemon refuses a request over
+/// its size limit after reading only the length prefix, answering and hanging
+/// up, so a write that failed can still have a response to read.
+fn exchange(stream: &mut UnixStream, request: &Request) -> Result<Response> {
+    let written = clipto_ipc::write_frame(stream, request);
+    match clipto_ipc::read_frame(stream) {
+        Ok(response) => Ok(response),
+        Err(e) => Err(written.err().unwrap_or(e)),
+    }
+}
+
+/// Whether `err`, from connecting to the socket, means no daemon is there.
+pub fn not_running(err: &io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused)
+}
+
+/// Clipboard change notifications, from `Client::subscribe`.
+pub struct Subscription {
+    stream: UnixStream,
+}
+
+impl Subscription {
+    /// A handle that ends this subscription from another thread: the
+    /// iterator then returns `None`.
+    pub fn closer(&self) -> Result<Closer> {
+        let stream = self.stream.try_clone().context("failed to clone the subscription")?;
+        Ok(Closer { stream })
+    }
+}
+
+/// Ends a `Subscription`, from `Subscription::closer`.
+pub struct Closer {
+    stream: UnixStream,
+}
+
+impl Closer {
+    pub fn close(&self) {
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Result<()>;
+
+    fn next(&mut self) -> Option<Result<()>> {
+        match clipto_ipc::read_frame(&mut self.stream) {
+            Ok(Response::Changed) => Some(Ok(())),
+            Ok(_) => Some(Err(anyhow::anyhow!("clipd: unexpected message in subscription"))),
+            Err(e) if is_eof(&e) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+fn is_eof(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == ErrorKind::UnexpectedEof)
+}
@@ -0,0 +1,15 @@
+//! Regenerates `include/clipto.h` from the `extern "C"` functions in
+//! `src/lib.rs`. The header is committed, so C users needn't run cargo to
+//! get it; cbindgen only rewrites it when it changes.
+
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))
+        .expect("invalid cbindgen.toml");
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("failed to generate the C header")
+        .write_to_file(format!("{crate_dir}/include/clipto.h"));
+}
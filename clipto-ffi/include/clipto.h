@@ -0,0 +1,49 @@
+/* Generated by cbindgen from clipto-ffi/src/lib.rs. Do not edit. */
+
+#ifndef CLIPTO_H
+#define CLIPTO_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Copy `len` bytes at `data` to the clipboard. `data` may be null when
+// `len` is 0.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes.
+int32_t clipto_copy(const uint8_t *data, size_t len);
+
+// Paste the current clipboard: on success `*data` points to `*len` bytes
+// that the caller releases with `clipto_free`. Nothing is allocated on
+// failure, e.g. when the clipboard is empty.
+//
+// # Safety
+//
+// `data` and `len` must be valid for writes.
+int32_t clipto_paste(uint8_t **data, size_t *len);
+
+// Release what `clipto_paste` returned. Null is ignored.
+//
+// # Safety
+//
+// `data` and `len` must be exactly what one `clipto_paste` call returned,
+// and not already freed.
+void clipto_free(uint8_t *data, size_t len);
+
+// Why the last failed call on this thread failed, as a NUL-terminated
+// string, or null if none has. Valid until the next failing call.
+const char *clipto_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CLIPTO_H */
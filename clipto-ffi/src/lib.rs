@@ -0,0 +1,91 @@
+//! C bindings for clipd, built as `libclipto_ffi.so` with the header in
+//! `include/clipto.h`. They wrap `clipto-client`, so the socket is found the
+//! way `clipto` finds it and throttled requests are retried.
+//!
+//! Functions return 0 on success and -1 on failure, after which
+//! `clipto_last_error` says why. Errors are per thread.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use clipto_client::{Client, CopyOptions};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, turning an error or a panic into -1 and `LAST_ERROR`.
+fn ffi(f: impl FnOnce() -> anyhow::Result<()>) -> i32 {
+    let message = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return 0,
+        Ok(Err(e)) => format!("{e:#}"),
+        Err(_) => "clipto: internal error (panic)".to_owned(),
+    };
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    -1
+}
+
+/// Copy `len` bytes at `data` to the clipboard. `data` may be null when
+/// `len` is 0.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn clipto_copy(data: *const u8, len: usize) -> i32 {
+    ffi(|| {
+        let payload = if len == 0 {
+            Vec::new()
+        } else {
+            // SAFETY: the caller guarantees `len` readable bytes at `data`.
+            unsafe { std::slice::from_raw_parts(data, len) }.to_vec()
+        };
+        Client::new()?.copy(payload, CopyOptions::default())
+    })
+}
+
+/// Paste the current clipboard: on success `*data` points to `*len` bytes
+/// that the caller releases with `clipto_free`. Nothing is allocated on
+/// failure, e.g. when the clipboard is empty.
+///
+/// # Safety
+///
+/// `data` and `len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn clipto_paste(data: *mut *mut u8, len: *mut usize) -> i32 {
+    ffi(|| {
+        let pasted = Client::new()?.paste()?.into_boxed_slice();
+        let n = pasted.len();
+        // SAFETY: the caller guarantees both are valid for writes.
+        unsafe {
+            *len = n;
+            *data = Box::into_raw(pasted).cast();
+        }
+        Ok(())
+    })
+}
+
+/// Release what `clipto_paste` returned. Null is ignored.
+///
+/// # Safety
+///
+/// `data` and `len` must be exactly what one `clipto_paste` call returned,
+/// and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn clipto_free(data: *mut u8, len: usize) {
+    if data.is_null() {
+        return;
+    }
+    // SAFETY: `data` is a `Box<[u8]>` of `len` bytes from `clipto_paste`.
+    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)) });
+}
+
+/// Why the last failed call on this thread failed, as a NUL-terminated
+/// string, or null if none has. Valid until the next failing call.
+#[no_mangle]
+pub extern "C" fn clipto_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
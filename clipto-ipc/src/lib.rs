@@ -1,58 +1,656 @@
 use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use bincode::Options;
 use serde::{Deserialize, Serialize};
 
+mod bytes;
+
 /// Where a copy request originated. Controls whether the daemon forwards the
-/// payload to the Wayland compositor via `wl-copy`.
+/// payload to the desktop clipboard (the Wayland compositor via `wl-copy`, or
+/// Windows under WSL).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CopySource {
     /// Originated from the user (e.g. tmux `y`). The daemon will sync to the
     /// Wayland compositor if a Wayland session is active.
     User,
-    /// Originated from the Wayland compositor (via `wl-paste --watch`). The
-    /// daemon stores it without forwarding back to avoid an infinite loop.
+    /// Originated from the desktop clipboard (via `wl-paste --watch`, or the
+    /// Windows clipboard watcher under WSL). The daemon stores it without
+    /// forwarding back to avoid an infinite loop.
     Wayland,
 }
 
+impl std::fmt::Display for CopySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CopySource::User => "user",
+            CopySource::Wayland => "wayland",
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Request {
-    Copy { payload: Vec<u8>, source: CopySource },
+    /// Store `payload` as the current clipboard. `mime` is the content type
+    /// to offer the compositor; `None` leaves the choice to `wl-copy`. A
+    /// `once` entry is destroyed after its first paste, from either side.
+    Copy {
+        #[serde(with = "bytes")]
+        payload: Vec<u8>,
+        source: CopySource,
+        mime: Option<String>,
+        once: bool,
+    },
     Paste,
+    /// Fetch history entry `index` (0 is the current clipboard).
+    Entry { index: u32 },
+    /// Describe history entry `index` without sending its content.
+    Info { index: u32 },
+    /// Make history entry `index` the current clipboard again.
+    Promote { index: u32 },
+    /// Pin (or unpin) history entry `index`, exempting it from eviction.
+    Pin { index: u32, pinned: bool },
+    /// List history entries with one-line previews: pinned entries first,
+    /// then the rest, each group newest first.
+    History,
+    /// List history entries whose content matches `query`, newest first.
+    Search { query: String, mode: SearchMode, ignore_case: bool },
+    /// Write history entry `index` to a daemon-managed temporary file instead
+    /// of sending it over the socket.
+    PasteFile { index: u32 },
+    /// Store `payload` in register `name` of `session`. Registers bypass the
+    /// history and the compositor.
+    SetRegister {
+        session: Session,
+        name: String,
+        #[serde(with = "bytes")]
+        payload: Vec<u8>,
+    },
+    /// Fetch register `name` of session `session`.
+    GetRegister { session: String, name: String },
+    /// Store `payload` as the clipboard of `namespace` (e.g. a tmux session),
+    /// replacing its last one. Namespaces bypass the history and the
+    /// compositor.
+    SetNamespace {
+        namespace: String,
+        #[serde(with = "bytes")]
+        payload: Vec<u8>,
+        mime: Option<String>,
+    },
+    /// Fetch the clipboard of `namespace`, or the current clipboard when the
+    /// namespace has none.
+    GetNamespace { namespace: String },
+    /// Every history entry in full, newest first, for `clipto export`.
+    Export,
+    /// Add `entries` (newest first) to the history, keeping their metadata.
+    Import { entries: Vec<ExportedEntry> },
+    Clear,
+    Stats,
+    /// Recent daemon warnings and errors.
+    Errors,
+    /// Which desktop clipboard the daemon syncs with, and how its watcher is
+    /// doing.
+    Status,
+    /// Keep the connection open: after `Response::Ok`, the daemon sends
+    /// `Response::Changed` each time the clipboard or history changes.
+    Subscribe,
+}
+
+/// A client-chosen scope for registers, e.g. `tmux:%3`. The daemon wipes the
+/// session's registers once process `pid` exits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub pid: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     Ok,
-    Payload { data: Vec<u8> },
+    Payload {
+        #[serde(with = "bytes")]
+        data: Vec<u8>,
+    },
+    /// A file the daemon wrote and will delete after its TTL.
+    File { path: PathBuf },
     Error { message: String },
+    Stats(Stats),
+    History(Vec<HistoryEntry>),
+    Errors(Vec<ErrorRecord>),
+    Export(Vec<ExportedEntry>),
+    Status(Status),
+    Info(EntryInfo),
+    /// The client is over its rate limit: nothing was done. Retry after this
+    /// many milliseconds.
+    Throttled { retry_after_ms: u64 },
+    /// Sent to subscribers on every change.
+    Changed,
+}
+
+/// Daemon counters since startup, returned by `Request::Stats`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub uptime_secs: u64,
+    /// Successful copies, from any source.
+    pub copies: u64,
+    pub pastes: u64,
+    /// Plaintext size of the current clipboard entry.
+    pub bytes_stored: u64,
+    /// Payloads forwarded to the desktop clipboard (`wl-copy`, or `clip.exe`
+    /// under WSL).
+    pub wayland_syncs: u64,
+    /// Connection, encryption, and Wayland sync failures.
+    pub errors: u64,
+    /// Copy requests the daemon refused to store.
+    pub rejected_payloads: u64,
+    /// Requests answered with `Response::Throttled`.
+    pub throttled: u64,
+}
+
+/// Returned by `Request::Status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    /// `wayland`, `windows` (WSL), or `pasteboard` (macOS).
+    pub backend: String,
+    /// The Wayland display synced with, e.g. `wayland-1`.
+    pub display: Option<String>,
+    pub watcher: WatcherState,
 }
 
-/// Path to the daemon's Unix socket: `$XDG_RUNTIME_DIR/clipto.sock`.
-pub fn socket_path() -> Result<PathBuf> {
+/// The child process that reports desktop clipboard changes (`wl-paste
+/// --watch`, or its WSL and macOS counterparts).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WatcherState {
+    /// Not running: nothing to watch yet.
+    Stopped,
+    Running { pid: u32, since_unix: u64, restarts: u32 },
+    /// Exited or failed to start; restarted at `retry_unix`.
+    Restarting { restarts: u32, last_exit: String, retry_unix: u64 },
+}
+
+/// How `Request::Search` interprets its query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchMode {
+    /// Plain substring.
+    Substring,
+    /// Regular expression (Rust `regex` syntax, linear-time matching).
+    Regex,
+}
+
+/// One history entry as listed by `Request::History`. Never carries the full
+/// payload; fetch it with `Request::Entry`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub index: u32,
+    pub source: CopySource,
+    pub pinned: bool,
+    /// Destroyed after its first paste; its preview is withheld.
+    pub once: bool,
+    /// Content type given at copy time, if any.
+    pub mime: Option<String>,
+    /// When the entry was stored, in seconds since the Unix epoch.
+    pub stored_unix: u64,
+    /// Who stored it: `comm[pid]` of the socket peer, or `wayland`,
+    /// `windows`, `pasteboard`, `dbus`, `klipper`.
+    pub client: String,
+    /// Plaintext size in bytes.
+    pub size: u64,
+    /// Single-line preview with control characters neutralized.
+    pub preview: String,
+}
+
+/// How `c` appears in a single-line, terminal-safe listing: newlines and tabs
+/// become visible markers, and anything that could drive or reorder the
+/// terminal (control characters, bidi overrides and isolates, the Unicode
+/// line and paragraph separators) becomes `�`.
+pub fn display_char(c: char) -> char {
+    match c {
+        '\n' => '⏎',
+        '\t' => ' ',
+        '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' | '\u{2028}' | '\u{2029}' => '�',
+        c if c.is_control() => '�',
+        c => c,
+    }
+}
+
+/// What `Request::Info` reports about a history entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryInfo {
+    pub index: u32,
+    pub source: CopySource,
+    pub pinned: bool,
+    pub once: bool,
+    /// Content type given at copy time, if any.
+    pub mime: Option<String>,
+    /// What the content looks like: `text`, `url`, `binary`, or an image
+    /// type sniffed from its magic number, such as `image/png`.
+    pub content: String,
+    /// Plaintext size in bytes.
+    pub size: u64,
+    /// Line count, for UTF-8 text.
+    pub lines: Option<u64>,
+    /// Hex SHA-256 of the plaintext; withheld for paste-once entries, whose
+    /// hash could confirm a guessed password.
+    pub sha256: Option<String>,
+    pub stored_unix: u64,
+    pub client: String,
+}
+
+/// A history entry with its full content, as moved by `Request::Export` and
+/// `Request::Import`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedEntry {
+    pub source: CopySource,
+    pub pinned: bool,
+    pub mime: Option<String>,
+    pub stored_unix: u64,
+    pub client: String,
+    #[serde(with = "bytes")]
+    pub data: Vec<u8>,
+}
+
+/// A warning or error the daemon logged, kept in a small in-memory ring.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorRecord {
+    /// Seconds since the Unix epoch.
+    pub unix_time: u64,
+    pub level: String,
+    /// Module that logged it, e.g. `clipd::notify`.
+    pub target: String,
+    pub message: String,
+}
+
+/// Per-user directory for sockets and short-lived files: `$XDG_RUNTIME_DIR`.
+#[cfg(not(target_os = "macos"))]
+pub fn runtime_dir() -> Result<PathBuf> {
     let dir = std::env::var("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR not set")?;
-    Ok(PathBuf::from(dir).join("clipto.sock"))
+    Ok(PathBuf::from(dir))
+}
+
+/// Per-user directory for sockets and short-lived files: `$TMPDIR`, which
+/// macOS points at an owner-only directory under `/var/folders`.
+#[cfg(target_os = "macos")]
+pub fn runtime_dir() -> Result<PathBuf> {
+    let dir = std::env::var("TMPDIR").context("TMPDIR not set")?;
+    Ok(PathBuf::from(dir))
+}
+
+/// Path to the config file shared by clipd and its clients:
+/// `$CLIPTO_CONFIG`, else `$XDG_CONFIG_HOME/clipto/config.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("CLIPTO_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    let base = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(std::env::var("HOME").ok()?).join(".config"),
+    };
+    Some(base.join("clipto").join("config.toml"))
+}
+
+/// Where the daemon's socket lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Path(PathBuf),
+    /// A name in Linux's abstract socket namespace, written `@name`. Needs no
+    /// directory, but has no file permissions either: the daemon checks the
+    /// peer's UID instead.
+    Abstract(String),
+}
+
+impl Endpoint {
+    /// `@name` is abstract, anything else a path.
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.strip_prefix('@') {
+            Some("") => bail!("empty abstract socket name"),
+            Some(name) => Ok(Endpoint::Abstract(name.to_owned())),
+            None => Ok(Endpoint::Path(PathBuf::from(s))),
+        }
+    }
+
+    pub fn connect(&self) -> std::io::Result<UnixStream> {
+        match self {
+            Endpoint::Path(path) => UnixStream::connect(path),
+            Endpoint::Abstract(name) => UnixStream::connect_addr(&abstract_addr(name)?),
+        }
+    }
+
+    /// Bind without any cleanup; replacing a stale socket file is the
+    /// caller's business.
+    pub fn bind(&self) -> std::io::Result<UnixListener> {
+        match self {
+            Endpoint::Path(path) => UnixListener::bind(path),
+            Endpoint::Abstract(name) => UnixListener::bind_addr(&abstract_addr(name)?),
+        }
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Path(path) => write!(f, "{}", path.display()),
+            Endpoint::Abstract(name) => write!(f, "@{name}"),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_addr(name: &str) -> std::io::Result<std::os::unix::net::SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    std::os::unix::net::SocketAddr::from_abstract_name(name)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn abstract_addr(_name: &str) -> std::io::Result<std::os::unix::net::SocketAddr> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "abstract sockets are Linux-only",
+    ))
+}
+
+/// The daemon's socket: `$CLIPTO_SOCKET` if set (e.g. on the far end of
+/// `clipto forward`), else the config file's `socket` key, else `clipto.sock`
+/// in `runtime_dir()`.
+pub fn socket() -> Result<Endpoint> {
+    if let Some(socket) = std::env::var("CLIPTO_SOCKET").ok().filter(|s| !s.is_empty()) {
+        return Endpoint::parse(&socket).context("invalid CLIPTO_SOCKET");
+    }
+    if let Some(socket) = client_config()?.socket {
+        return Endpoint::parse(&socket).context("invalid `socket` in the config");
+    }
+    Ok(Endpoint::Path(runtime_dir()?.join("clipto.sock")))
+}
+
+/// `[paste]` config table, read by `clipto paste`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasteConfig {
+    /// When to strip terminal control sequences from pasted text.
+    pub sanitize: Sanitize,
+    /// When clipd isn't running, read the desktop clipboard directly
+    /// (`wl-paste`, `xclip`, OSC 52) instead of failing.
+    pub fallback: bool,
 }
 
-/// Write a length-prefixed bincode frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitize {
+    /// When stdout is a terminal.
+    #[default]
+    Tty,
+    Always,
+    Never,
+}
+
+/// `[namespace]` config table, read by `clipto copy` and `clipto paste`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamespaceConfig {
+    /// Inside tmux, copy to and paste from the tmux session's namespace
+    /// unless told otherwise.
+    pub tmux: bool,
+}
+
+/// The config keys clients read. Every other key is ignored here (clipd
+/// validates those).
+#[derive(Default, Deserialize)]
+struct ClientConfig {
+    socket: Option<String>,
+    #[serde(default)]
+    paste: PasteConfig,
+    #[serde(default)]
+    namespace: NamespaceConfig,
+}
+
+fn client_config() -> Result<ClientConfig> {
+    let Some(path) = config_path() else {
+        return Ok(ClientConfig::default());
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ClientConfig::default()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    toml::from_str(&text).with_context(|| format!("invalid config in {}", path.display()))
+}
+
+/// The config file's `[paste]` table.
+pub fn paste_config() -> Result<PasteConfig> {
+    Ok(client_config()?.paste)
+}
+
+/// The config file's `[namespace]` table.
+pub fn namespace_config() -> Result<NamespaceConfig> {
+    Ok(client_config()?.namespace)
+}
+
+/// The desktop clipboard type to fetch, out of those `offered`, per the
+/// daemon's `[watch]` table: the first offered type matching a `prefer`
+/// pattern (in pattern order), else the first offered MIME type matching no
+/// `deny` pattern. X11 target names that XWayland apps also offer
+/// (`UTF8_STRING`, `TARGETS`) are only taken when explicitly preferred.
+pub fn choose_type<'a>(
+    offered: &[&'a str],
+    prefer: &[String],
+    deny: &[String],
+) -> Option<&'a str> {
+    prefer
+        .iter()
+        .find_map(|p| offered.iter().find(|t| type_matches(p, t)))
+        .or_else(|| {
+            offered
+                .iter()
+                .find(|t| t.contains('/') && !deny.iter().any(|p| type_matches(p, t)))
+        })
+        .copied()
+}
+
+/// `pattern` is `*`, `major/*`, or an exact type; comparison ignores case.
+fn type_matches(pattern: &str, mime: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.strip_suffix("/*") {
+        Some(major) => mime
+            .split_once('/')
+            .is_some_and(|(m, _)| m.eq_ignore_ascii_case(major)),
+        None => pattern.eq_ignore_ascii_case(mime),
+    }
+}
+
+/// Path to the optional plain-text line socket: `clipto-line.sock` in
+/// `runtime_dir()`.
+pub fn line_socket_path() -> Result<PathBuf> {
+    Ok(runtime_dir()?.join("clipto-line.sock"))
+}
+
+/// Write a length-prefixed bincode frame. Frames carry plaintext, so the
+/// message is serialized straight into `writer` rather than into a frame
+/// buffer: a payload goes out in one `write_all` from where it already is,
+/// and an unbuffered `writer` (a `UnixStream`) leaves no copy of it behind.
 pub fn write_frame<T: Serialize>(writer: &mut impl Write, msg: &T) -> Result<()> {
-    let bytes = bincode::serialize(msg).context("serialization failed")?;
-    let len = u32::try_from(bytes.len())
-        .context("frame too large")?
-        .to_le_bytes();
-    writer.write_all(&len)?;
-    writer.write_all(&bytes)?;
+    let size = bincode::serialized_size(msg).context("serialization failed")?;
+    let len = u32::try_from(size).context("frame too large")?;
+    writer.write_all(&len.to_le_bytes())?;
+    bincode::serialize_into(&mut *writer, msg).map_err(|e| io_or(*e, "serialization failed"))?;
     writer.flush()?;
     Ok(())
 }
 
 /// Read a length-prefixed bincode frame.
 pub fn read_frame<T: for<'de> Deserialize<'de>>(reader: &mut impl Read) -> Result<T> {
+    read_frame_max(reader, u32::MAX)
+}
+
+/// A frame whose length prefix is over the reader's limit. Nothing past the
+/// prefix has been read.
+#[derive(Debug)]
+pub struct FrameTooLong {
+    pub len: u32,
+    pub max: u32,
+}
+
+impl std::fmt::Display for FrameTooLong {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "frame of {} bytes is over the {}-byte limit", self.len, self.max)
+    }
+}
+
+impl std::error::Error for FrameTooLong {}
+
+/// `read_frame`, failing with `FrameTooLong` when the length prefix says more
+/// than `max` bytes, before any of them are read.
+///
+/// The message is deserialized straight from `reader`, without a frame
+/// buffer: a payload is read into one allocation of exactly its length,
+/// which becomes the message's `Vec<u8>` for the caller to zeroize, and an
+/// unbuffered `reader` (a `UnixStream`) leaves no other copy of it behind.
+pub fn read_frame_max<T: for<'de> Deserialize<'de>>(
+    reader: &mut impl Read,
+    max: u32,
+) -> Result<T> {
     let mut len_buf = [0u8; 4];
     reader.read_exact(&mut len_buf)?;
-    let len = u32::from_le_bytes(len_buf) as usize;
-    let mut buf = vec![0u8; len];
-    reader.read_exact(&mut buf)?;
-    bincode::deserialize(&buf).context("deserialization failed")
+    let len = u32::from_le_bytes(len_buf);
+    if len > max {
+        return Err(FrameTooLong { len, max }.into());
+    }
+    let mut frame = reader.take(u64::from(len));
+    // The limit makes a length field claiming more than the frame holds an
+    // error before anything is allocated for it. Otherwise these are the
+    // options `bincode::serialize` uses.
+    let msg = bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(u64::from(len))
+        .deserialize_from(&mut frame)
+        .map_err(|e| io_or(*e, "deserialization failed"))?;
+    if frame.limit() > 0 {
+        bail!("deserialization failed: {} bytes left over in the frame", frame.limit());
+    }
+    Ok(msg)
+}
+
+/// `err` as the `io::Error` it wraps, if it wraps one, so that a hangup or
+/// timeout mid-frame can be told apart from a malformed message.
+fn io_or(err: bincode::ErrorKind, context: &'static str) -> anyhow::Error {
+    match err {
+        bincode::ErrorKind::Io(e) => e.into(),
+        e => anyhow::Error::new(e).context(context),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+
+    use super::*;
+
+    fn copy(payload: &[u8]) -> Request {
+        Request::Copy {
+            payload: payload.to_vec(),
+            source: CopySource::User,
+            mime: None,
+            once: false,
+        }
+    }
+
+    fn frame(msg: &Request) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, msg).unwrap();
+        buf
+    }
+
+    #[test]
+    fn frame_round_trips() {
+        let buf = frame(&copy(b"hello"));
+        let msg: Request = read_frame(&mut Cursor::new(buf)).unwrap();
+        assert!(matches!(msg, Request::Copy { payload, .. } if payload == b"hello"));
+    }
+
+    #[test]
+    fn oversize_prefix_is_rejected_before_reading_the_frame() {
+        let mut buf = u32::MAX.to_le_bytes().to_vec();
+        buf.extend_from_slice(&[0; 16]);
+        let mut reader = Cursor::new(buf);
+        let err = read_frame_max::<Request>(&mut reader, 1024).unwrap_err();
+        let too_long = err.downcast_ref::<FrameTooLong>().unwrap();
+        assert_eq!((too_long.len, too_long.max), (u32::MAX, 1024));
+        assert_eq!(reader.position(), 4);
+    }
+
+    #[test]
+    fn truncated_frame_is_an_eof() {
+        let mut buf = frame(&copy(b"hello"));
+        buf.pop();
+        let err = read_frame_max::<Request>(&mut Cursor::new(buf), 1024).unwrap_err();
+        let io = err.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn frame_at_the_limit_is_accepted() {
+        let buf = frame(&copy(&[7; 100]));
+        let len = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        let msg: Request = read_frame_max(&mut Cursor::new(&buf), len).unwrap();
+        assert!(matches!(msg, Request::Copy { payload, .. } if payload == [7; 100]));
+        let err = read_frame_max::<Request>(&mut Cursor::new(&buf), len - 1).unwrap_err();
+        assert!(err.is::<FrameTooLong>());
+    }
+
+    #[test]
+    fn length_field_past_the_frame_is_rejected() {
+        // A `Copy` whose payload claims to be longer than the 12-byte frame.
+        let mut buf = 12u32.to_le_bytes().to_vec();
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&u64::MAX.to_le_bytes());
+        let err = read_frame_max::<Request>(&mut Cursor::new(buf), 1024).unwrap_err();
+        assert!(err.to_string().contains("deserialization failed"));
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    const OFFERED: [&str; 5] =
+        ["TARGETS", "UTF8_STRING", "text/html", "text/plain;charset=utf-8", "image/png"];
+
+    #[test]
+    fn first_mime_type_without_preferences() {
+        assert_eq!(choose_type(&OFFERED, &[], &[]), Some("text/html"));
+    }
+
+    #[test]
+    fn preferences_go_in_pattern_order() {
+        let prefer = patterns(&["image/*", "text/plain;charset=utf-8"]);
+        assert_eq!(choose_type(&OFFERED, &prefer, &[]), Some("image/png"));
+        let prefer = patterns(&["application/pdf", "TEXT/PLAIN;charset=UTF-8"]);
+        assert_eq!(choose_type(&OFFERED, &prefer, &[]), Some("text/plain;charset=utf-8"));
+    }
+
+    #[test]
+    fn denied_types_are_skipped() {
+        let deny = patterns(&["text/html", "TEXT/*"]);
+        assert_eq!(choose_type(&OFFERED, &[], &deny), Some("image/png"));
+        assert_eq!(choose_type(&OFFERED, &[], &patterns(&["*"])), None);
+    }
+
+    #[test]
+    fn preference_overrides_deny() {
+        let prefer = patterns(&["text/html"]);
+        let deny = patterns(&["text/*"]);
+        assert_eq!(choose_type(&OFFERED, &prefer, &deny), Some("text/html"));
+    }
+
+    #[test]
+    fn x11_targets_only_when_preferred() {
+        assert_eq!(choose_type(&["TARGETS", "UTF8_STRING"], &[], &[]), None);
+        let prefer = patterns(&["utf8_string"]);
+        assert_eq!(choose_type(&OFFERED, &prefer, &[]), Some("UTF8_STRING"));
+        // `major/*` needs a major type.
+        assert_eq!(choose_type(&["UTF8_STRING"], &patterns(&["UTF8_STRING/*"]), &[]), None);
+    }
 }
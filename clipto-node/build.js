@@ -0,0 +1,6 @@
+// Copy the addon cargo built next to index.js as clipto.node.
+const fs = require("fs");
+const path = require("path");
+
+const lib = process.platform === "darwin" ? "libclipto_node.dylib" : "libclipto_node.so";
+fs.copyFileSync(path.join(__dirname, "..", "target", "release", lib), path.join(__dirname, "clipto.node"));
@@ -0,0 +1,24 @@
+export interface CopyOptions {
+  /** Content type to offer GUI apps, e.g. `image/png`. */
+  mime?: string;
+  /** Destroy the entry after its first paste. */
+  once?: boolean;
+}
+
+/** Make `data` the current clipboard. */
+export function copy(data: Buffer, options?: CopyOptions): Promise<void>;
+
+/** The current clipboard. Rejects when it is empty. */
+export function paste(): Promise<Buffer>;
+
+/** A live `subscribe`. It keeps the process alive until closed. */
+export class Subscription {
+  /** Stop calling back. Safe to call more than once. */
+  close(): void;
+}
+
+/**
+ * Call `callback(null)` on every clipboard change, or `callback(err)` once if
+ * the subscription fails. Fetch what changed with `paste`.
+ */
+export function subscribe(callback: (err: Error | null) => void): Subscription;
@@ -0,0 +1,2 @@
+// Built by `npm run build`; see README.md.
+module.exports = require("./clipto.node");
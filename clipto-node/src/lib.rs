@@ -0,0 +1,120 @@
+//! Node.js bindings for clipd, built with napi-rs as `clipto.node` and
+//! loaded through `index.js`. They wrap `clipto-client`, so the socket is
+//! found the way `clipto` finds it and throttled requests are retried.
+//!
+//! `copy` and `paste` run on libuv's thread pool and return promises, so an
+//! Electron app or VS Code extension never blocks its event loop on the
+//! socket.
+
+use clipto_client::{Client, Closer, CopyOptions as ClientCopyOptions};
+use napi::bindgen_prelude::{AsyncTask, Buffer};
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{Env, Error, JsFunction, Result, Status, Task};
+use napi_derive::napi;
+
+fn js_error(err: anyhow::Error) -> Error {
+    Error::new(Status::GenericFailure, format!("{err:#}"))
+}
+
+fn client() -> Result<Client> {
+    Client::new().map_err(js_error)
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct CopyOptions {
+    /// Content type to offer GUI apps, e.g. `image/png`.
+    pub mime: Option<String>,
+    /// Destroy the entry after its first paste.
+    pub once: Option<bool>,
+}
+
+pub struct Copy {
+    payload: Vec<u8>,
+    options: ClientCopyOptions,
+}
+
+impl Task for Copy {
+    type Output = ();
+    type JsValue = ();
+
+    fn compute(&mut self) -> Result<()> {
+        let payload = std::mem::take(&mut self.payload);
+        client()?.copy(payload, self.options.clone()).map_err(js_error)
+    }
+
+    fn resolve(&mut self, _env: Env, (): ()) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Make `data` the current clipboard.
+#[napi(ts_return_type = "Promise<void>")]
+pub fn copy(data: Buffer, options: Option<CopyOptions>) -> AsyncTask<Copy> {
+    let options = options.unwrap_or_default();
+    AsyncTask::new(Copy {
+        payload: data.to_vec(),
+        options: ClientCopyOptions {
+            mime: options.mime,
+            once: options.once.unwrap_or(false),
+            ..ClientCopyOptions::default()
+        },
+    })
+}
+
+pub struct Paste;
+
+impl Task for Paste {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Vec<u8>> {
+        client()?.paste().map_err(js_error)
+    }
+
+    fn resolve(&mut self, _env: Env, data: Vec<u8>) -> Result<Buffer> {
+        Ok(data.into())
+    }
+}
+
+/// The current clipboard. Rejects when it is empty.
+#[napi(ts_return_type = "Promise<Buffer>")]
+pub fn paste() -> AsyncTask<Paste> {
+    AsyncTask::new(Paste)
+}
+
+/// A live `subscribe`. It keeps the process alive until closed.
+#[napi]
+pub struct Subscription {
+    closer: Closer,
+}
+
+#[napi]
+impl Subscription {
+    /// Stop calling back. Safe to call more than once.
+    #[napi]
+    pub fn close(&self) {
+        self.closer.close();
+    }
+}
+
+/// Call `callback(null)` on every clipboard change, or `callback(err)` once
+/// if the subscription fails. Fetch what changed with `paste`.
+#[napi(ts_args_type = "callback: (err: Error | null) => void")]
+pub fn subscribe(callback: JsFunction) -> Result<Subscription> {
+    let subscription = client()?.subscribe().map_err(js_error)?;
+    let closer = subscription.closer().map_err(js_error)?;
+    let notify: ThreadsafeFunction<(), ErrorStrategy::CalleeHandled> =
+        callback.create_threadsafe_function(0, |_| Ok(Vec::<()>::new()))?;
+    std::thread::spawn(move || {
+        for change in subscription {
+            let change = change.map_err(js_error);
+            let failed = change.is_err();
+            notify.call(change, ThreadsafeFunctionCallMode::NonBlocking);
+            if failed {
+                break;
+            }
+        }
+    });
+    Ok(Subscription { closer })
+}
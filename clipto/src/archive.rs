@@ -0,0 +1,137 @@
+//! `clipto export` / `clipto import`: the history as an age-encrypted file.
+//!
+//! The archive is encrypted in memory before anything is written, to a
+//! passphrase or to age recipients, so plaintext never touches disk.
+//! `clipto share` and `clipto receive` use the same helpers for one entry.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use age::armor::{ArmoredReader, ArmoredWriter, Format};
+use age::secrecy::SecretString;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use clipto_ipc::{ExportedEntry, Request, Response};
+
+use crate::{request, write_atomic};
+
+/// Bumped on incompatible changes to `Archive`.
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    version: u32,
+    /// Newest first.
+    entries: Vec<ExportedEntry>,
+}
+
+/// Write the daemon's history to `output`, encrypted to `recipients` (age
+/// public keys) or, if there are none, to a passphrase.
+pub fn export(output: &Path, recipients: &[String]) -> Result<usize> {
+    let entries = match request(&Request::Export)? {
+        Response::Export(entries) => entries,
+        Response::Error { message } => bail!("clipd: {message}"),
+        _ => bail!("clipd: unexpected response to Export"),
+    };
+    let count = entries.len();
+    let plaintext = bincode::serialize(&Archive { version: VERSION, entries })?;
+
+    let ciphertext = encrypt(&plaintext, recipients, false)?;
+    write_atomic(output, &ciphertext)?;
+    Ok(count)
+}
+
+/// Add the entries in `input` to the daemon's history, decrypting with the
+/// age identity files in `identities` or, if there are none, a passphrase.
+pub fn import(input: &Path, identities: &[PathBuf]) -> Result<usize> {
+    let ciphertext =
+        fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+    let plaintext = decrypt(&ciphertext, identities, input)?;
+
+    let archive: Archive = bincode::deserialize(&plaintext).context("corrupt archive")?;
+    if archive.version != VERSION {
+        bail!("unsupported archive version {}", archive.version);
+    }
+    let count = archive.entries.len();
+    match request(&Request::Import { entries: archive.entries })? {
+        Response::Ok => Ok(count),
+        Response::Error { message } => bail!("clipd: {message}"),
+        _ => bail!("clipd: unexpected response to Import"),
+    }
+}
+
+/// Encrypt `plaintext` to `recipients` (age public keys) or, if there are
+/// none, to a passphrase. `armor` makes it ASCII, for pasting into chat.
+pub fn encrypt(plaintext: &[u8], recipients: &[String], armor: bool) -> Result<Vec<u8>> {
+    let encryptor = if recipients.is_empty() {
+        age::Encryptor::with_user_passphrase(passphrase(true)?)
+    } else {
+        let recipients = recipients
+            .iter()
+            .map(|r| {
+                age::x25519::Recipient::from_str(r).map_err(|e| anyhow!("recipient {r}: {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?
+    };
+    let format = if armor { Format::AsciiArmor } else { Format::Binary };
+    let mut ciphertext = Vec::new();
+    let mut writer = encryptor.wrap_output(ArmoredWriter::wrap_output(&mut ciphertext, format)?)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?.finish()?;
+    Ok(ciphertext)
+}
+
+/// Decrypt `ciphertext` (binary or armored), read from `source`, with the age
+/// identity files in `identities` or, if it was encrypted to one, a
+/// passphrase.
+pub fn decrypt(ciphertext: &[u8], identities: &[PathBuf], source: &Path) -> Result<Vec<u8>> {
+    let decryptor = age::Decryptor::new_buffered(ArmoredReader::new(ciphertext))
+        .with_context(|| format!("{} is not an age file", source.display()))?;
+
+    let mut reader = if decryptor.is_scrypt() {
+        let identity = age::scrypt::Identity::new(passphrase(false)?);
+        decryptor
+            .decrypt(iter::once(&identity as &dyn age::Identity))
+            .context("wrong passphrase")?
+    } else {
+        if identities.is_empty() {
+            bail!("{} is encrypted to public keys; pass --identity", source.display());
+        }
+        let mut keys = Vec::new();
+        for path in identities {
+            let file = age::IdentityFile::from_file(path.to_string_lossy().into_owned())
+                .with_context(|| format!("failed to read identity {}", path.display()))?;
+            keys.extend(file.into_identities()?);
+        }
+        decryptor
+            .decrypt(keys.iter().map(|k| k.as_ref()))
+            .context("none of the identities can decrypt this file")?
+    };
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+/// `$CLIPTO_PASSPHRASE`, or a prompt on the terminal (twice when `confirm`).
+fn passphrase(confirm: bool) -> Result<SecretString> {
+    if let Ok(passphrase) = std::env::var("CLIPTO_PASSPHRASE") {
+        return Ok(passphrase.into());
+    }
+    let first = rpassword::prompt_password("Passphrase: ").context("failed to read passphrase")?;
+    if first.is_empty() {
+        bail!("empty passphrase");
+    }
+    if confirm {
+        let second = rpassword::prompt_password("Confirm passphrase: ")
+            .context("failed to read passphrase")?;
+        if first != second {
+            bail!("passphrases don't match");
+        }
+    }
+    Ok(first.into())
+}
@@ -0,0 +1,71 @@
+//! `clipto daemon`: clipd, run in this process with `--foreground` (as the
+//! systemd unit does), otherwise started detached — the same way
+//! `--spawn-daemon` starts it on first use.
+
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use clipto_ipc::{Endpoint, Request, Response};
+
+/// How long a detached daemon gets to start answering.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn run(foreground: bool) -> Result<()> {
+    if foreground {
+        return clipd::run();
+    }
+    let endpoint = clipto_ipc::socket()?;
+    if ping(&endpoint) {
+        bail!("clipd is already running at {endpoint}");
+    }
+    spawn(&endpoint)
+}
+
+/// Start `clipto daemon --foreground` in its own session with its stdio on
+/// `/dev/null`, and wait until it answers on `endpoint`. Logs go nowhere
+/// unless `log_file` is set.
+pub fn spawn(endpoint: &Endpoint) -> Result<()> {
+    let exe = std::env::current_exe().context("failed to locate the clipto executable")?;
+    let mut cmd = Command::new(exe);
+    cmd.args(["daemon", "--foreground"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid is async-signal-safe.
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn().context("failed to start clipd")?;
+
+    let deadline = Instant::now() + START_TIMEOUT;
+    loop {
+        if ping(endpoint) {
+            return Ok(());
+        }
+        if let Some(status) = child.try_wait()? {
+            bail!("clipd exited with {status}; run `clipto daemon --foreground` to see why");
+        }
+        if Instant::now() >= deadline {
+            bail!("clipd didn't answer on {endpoint} within {}s", START_TIMEOUT.as_secs());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Whether a daemon answers on `endpoint`. Asks for its status, so the probe
+/// isn't logged as a broken connection.
+fn ping(endpoint: &Endpoint) -> bool {
+    let Ok(mut stream) = endpoint.connect() else {
+        return false;
+    };
+    clipto_ipc::write_frame(&mut stream, &Request::Status).is_ok()
+        && clipto_ipc::read_frame::<Response>(&mut stream).is_ok()
+}
@@ -0,0 +1,114 @@
+//! `clipto edit`: open a history entry in `$VISUAL`/`$EDITOR` and copy the
+//! result back.
+//!
+//! On Linux the content lives in a memfd, never in a named file on disk. The
+//! editor opens it as `/proc/<pid>/fd/<n>`, which stays valid for as long as
+//! we hold the descriptor. Elsewhere it is an owner-only file in the runtime
+//! directory, removed afterwards.
+
+use std::fs::File;
+use std::io::Write;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use clipto_client::CopyOptions;
+use clipto_ipc::{Request, Response};
+
+use crate::{client, request};
+
+pub fn run(index: u32) -> Result<()> {
+    let req = if index == 0 { Request::Paste } else { Request::Entry { index } };
+    let original = match request(&req)? {
+        Response::Payload { data } => data,
+        Response::Error { message } => bail!("clipd: {message}"),
+        _ => bail!("clipd: unexpected response to Paste"),
+    };
+
+    let mut scratch = Scratch::new()?;
+    scratch.file.write_all(&original)?;
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    // Through the shell, so `EDITOR="code --wait"` works.
+    let status = Command::new("sh")
+        .args(["-c", &format!("{editor} \"$1\""), "sh", &scratch.path])
+        .status()
+        .with_context(|| format!("failed to run {editor}"))?;
+    if !status.success() {
+        bail!("{editor} exited with {status}; clipboard left unchanged");
+    }
+
+    let edited = scratch.read()?;
+    if edited == original {
+        eprintln!("unchanged");
+        return Ok(());
+    }
+
+    client()?.copy(edited, CopyOptions::default())
+}
+
+/// The file the editor works on, and the path it opens it by.
+struct Scratch {
+    file: File,
+    path: String,
+}
+
+#[cfg(target_os = "linux")]
+impl Scratch {
+    /// An anonymous, memory-backed file. Close-on-exec: the editor reopens it
+    /// by path rather than inheriting it.
+    fn new() -> Result<Self> {
+        use std::os::fd::{AsRawFd, FromRawFd};
+
+        let name = std::ffi::CString::new("clipto-edit")?;
+        // SAFETY: `name` is a valid NUL-terminated string.
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("memfd_create failed");
+        }
+        // SAFETY: `fd` was just created and is owned by nobody else.
+        let file = unsafe { File::from_raw_fd(fd) };
+        let path = format!("/proc/{}/fd/{}", std::process::id(), file.as_raw_fd());
+        Ok(Self { file, path })
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut data = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Scratch {
+    fn new() -> Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let path = clipto_ipc::runtime_dir()?.join(format!("clipto-edit.{}", std::process::id()));
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        Ok(Self { file, path: path.to_string_lossy().into_owned() })
+    }
+
+    /// By path: editors that save by renaming a new file over the old one
+    /// leave our descriptor pointing at the original.
+    fn read(&mut self) -> Result<Vec<u8>> {
+        Ok(std::fs::read(&self.path)?)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,139 @@
+//! `clipto paste --fallback`: when clipd isn't running, read the desktop
+//! clipboard directly instead of failing, trying `wl-paste`, then
+//! `xclip -o`, then an OSC 52 query to the terminal.
+//!
+//! Each step is skipped where it can't work (no `WAYLAND_DISPLAY`, no
+//! `DISPLAY`, no controlling terminal), and one that fails or finds the
+//! clipboard empty passes to the next.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+
+/// How long to wait for the terminal to answer the OSC 52 query. Terminals
+/// that don't support it never answer.
+const OSC52_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The clipboard from the first source that has it, and that source's name.
+pub fn paste() -> Result<(Vec<u8>, &'static str)> {
+    if set("WAYLAND_DISPLAY") {
+        if let Some(data) = run("wl-paste", &["--no-newline"]) {
+            return Ok((data, "wl-paste"));
+        }
+    }
+    if set("DISPLAY") {
+        if let Some(data) = run("xclip", &["-selection", "clipboard", "-o"]) {
+            return Ok((data, "xclip"));
+        }
+    }
+    match osc52() {
+        Ok(Some(data)) => Ok((data, "OSC 52")),
+        Ok(None) => bail!("no fallback had the clipboard (tried wl-paste, xclip and OSC 52)"),
+        Err(e) => Err(e.context("no fallback had the clipboard (tried wl-paste and xclip)")),
+    }
+}
+
+fn set(var: &str) -> bool {
+    std::env::var_os(var).is_some_and(|v| !v.is_empty())
+}
+
+/// `program`'s stdout if it succeeded with some.
+fn run(program: &str, args: &[&str]) -> Option<Vec<u8>> {
+    let out = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    (out.status.success() && !out.stdout.is_empty()).then_some(out.stdout)
+}
+
+/// Ask the terminal for its clipboard with `OSC 52 ; c ; ?`. `None` when it
+/// doesn't answer in time or answers with nothing.
+fn osc52() -> Result<Option<Vec<u8>>> {
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("no terminal to query")?;
+    let raw = RawMode::enable(&tty)?;
+    tty.write_all(b"\x1b]52;c;?\x07").context("failed to write to the terminal")?;
+
+    let deadline = Instant::now() + OSC52_TIMEOUT;
+    let mut reply = Vec::new();
+    while !ends_string(&reply) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || !readable(&tty, left) {
+            return Ok(None);
+        }
+        let mut buf = [0; 4096];
+        match tty.read(&mut buf).context("failed to read from the terminal")? {
+            0 => return Ok(None),
+            n => reply.extend_from_slice(&buf[..n]),
+        }
+    }
+    drop(raw);
+
+    // ESC ] 52 ; c ; <base64> BEL (or ST)
+    let Some(start) = reply.windows(4).position(|w| w == b"]52;") else {
+        return Ok(None);
+    };
+    let body = &reply[start + 4..];
+    let Some(semicolon) = body.iter().position(|&b| b == b';') else {
+        return Ok(None);
+    };
+    let encoded: Vec<u8> =
+        body[semicolon + 1..].iter().copied().take_while(|&b| b != 0x07 && b != 0x1b).collect();
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .context("invalid OSC 52 reply")?;
+    Ok((!data.is_empty()).then_some(data))
+}
+
+/// Whether `reply` holds a whole string sequence, ended by BEL or ST.
+fn ends_string(reply: &[u8]) -> bool {
+    reply.ends_with(b"\x07") || reply.ends_with(b"\x1b\\")
+}
+
+fn readable(tty: &File, timeout: Duration) -> bool {
+    let mut fds = libc::pollfd { fd: tty.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    let ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: one valid pollfd.
+    unsafe { libc::poll(&mut fds, 1, ms) > 0 }
+}
+
+/// The terminal in non-canonical, no-echo mode, so the reply can be read
+/// without Enter and doesn't show up on screen. Restored on drop.
+struct RawMode {
+    fd: i32,
+    saved: libc::termios,
+}
+
+impl RawMode {
+    fn enable(tty: &File) -> Result<Self> {
+        let fd = tty.as_raw_fd();
+        // SAFETY: termios is plain data, filled in by tcgetattr.
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } == -1 {
+            return Err(std::io::Error::last_os_error()).context("tcgetattr");
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } == -1 {
+            return Err(std::io::Error::last_os_error()).context("tcsetattr");
+        }
+        Ok(Self { fd, saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores what tcgetattr returned for the same fd.
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved) };
+    }
+}
@@ -0,0 +1,92 @@
+//! `clipto forward <host>`: an SSH session with clipd's socket forwarded to
+//! the remote host (`ssh -R`), so `clipto` there copies into and pastes from
+//! this machine's clipboard.
+//!
+//! sshd never removes a forwarded socket when the session ends, and refuses
+//! to bind over the leftover one unless the server sets
+//! `StreamLocalBindUnlink yes`. So every session first deletes the stale
+//! socket in a separate `ssh` call (one more authentication, or none with
+//! `ControlMaster`).
+
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use clipto_ipc::Endpoint;
+
+pub fn run(
+    host: &str,
+    remote_socket: Option<String>,
+    print: bool,
+    ssh_args: &[String],
+) -> Result<()> {
+    let local = match clipto_ipc::socket()? {
+        Endpoint::Path(path) => path,
+        Endpoint::Abstract(name) => {
+            bail!("ssh can't forward the abstract socket @{name}; point clipd at a path")
+        }
+    };
+    let local = local.to_str().context("socket path is not UTF-8")?;
+    let remote = match remote_socket {
+        Some(path) => path,
+        None => default_remote_socket()?,
+    };
+
+    if print {
+        print_config(host, &remote, local);
+        return Ok(());
+    }
+
+    let status = Command::new("ssh")
+        .args(ssh_args)
+        .arg(host)
+        .args(["rm", "-f", "--", &quote(&remote)])
+        .status()
+        .context("failed to run ssh")?;
+    if !status.success() {
+        bail!("removing a stale {remote} on {host} failed: ssh exited with {status}");
+    }
+
+    // Exits with an error if the socket can't be bound, rather than opening a
+    // shell whose `clipto` silently talks to nothing (or to someone else's
+    // socket at that path).
+    let shell = format!("CLIPTO_SOCKET={} exec \"$SHELL\" -l", quote(&remote));
+    let err = Command::new("ssh")
+        .args(["-t", "-o", "ExitOnForwardFailure=yes", "-R"])
+        .arg(format!("{remote}:{local}"))
+        .args(ssh_args)
+        .arg(host)
+        .arg(shell)
+        .exec();
+    Err(err).context("failed to run ssh")
+}
+
+/// `/tmp/clipto-$USER.sock`. sshd creates it owner-only (`StreamLocalBindMask`
+/// defaults to 0177).
+fn default_remote_socket() -> Result<String> {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .ok()
+        .context("$USER is not set; pass --remote-socket")?;
+    Ok(format!("/tmp/clipto-{user}.sock"))
+}
+
+fn print_config(host: &str, remote: &str, local: &str) {
+    println!("# ~/.ssh/config");
+    println!("Host {host}");
+    println!("    RemoteForward {remote} {local}");
+    println!("    ExitOnForwardFailure yes");
+    println!("    # needs `AcceptEnv CLIPTO_SOCKET` in the server's sshd_config;");
+    println!("    # otherwise export CLIPTO_SOCKET={remote} in the remote shell");
+    println!("    SetEnv CLIPTO_SOCKET={remote}");
+    println!();
+    println!("# and in the server's sshd_config, so a stale socket from an earlier");
+    println!("# session doesn't block the forward:");
+    println!("#   StreamLocalBindUnlink yes");
+}
+
+/// Single-quote `s` for the remote shell.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
@@ -0,0 +1,112 @@
+use std::path::Path;
+
+/// Elisp wiring Emacs' kill ring to clipd's line socket. `@SOCKET@` is
+/// replaced with the socket path at generation time.
+const EMACS: &str = r#";;; clipto.el --- generated by `clipto integrate emacs`
+;;
+;; Requires `line_socket = true` in ~/.config/clipto/config.toml.
+;; Load from your init file: (load "~/.config/emacs/clipto.el")
+
+(defvar clipto-socket "@SOCKET@"
+  "Path to clipd's line-protocol socket.")
+
+(defvar clipto--last-cut nil
+  "Text most recently sent by `clipto-cut', so paste can skip our own echo.")
+
+(defun clipto--request (command &optional payload)
+  "Send COMMAND (and PAYLOAD) to clipd; return the reply body or nil."
+  (let* ((buf (generate-new-buffer " *clipto*"))
+         (proc (make-network-process :name "clipto" :buffer buf
+                                     :family 'local :service clipto-socket
+                                     :coding 'binary :noquery t)))
+    (unwind-protect
+        (progn
+          (process-send-string proc (concat command "\n"))
+          (when payload (process-send-string proc payload))
+          (process-send-eof proc)
+          (while (process-live-p proc)
+            (accept-process-output proc 0.05))
+          (with-current-buffer buf
+            (let ((reply (buffer-string)))
+              (if (string-prefix-p "OK\n" reply)
+                  (substring reply 3)
+                (message "clipto: %s" (string-trim reply))
+                nil))))
+      (kill-buffer buf))))
+
+(defun clipto-cut (text)
+  "Store TEXT in clipd. Suitable for `interprogram-cut-function'."
+  (setq clipto--last-cut text)
+  (clipto--request "COPY" (encode-coding-string text 'utf-8)))
+
+(defun clipto-paste ()
+  "Return clipd's text unless it is what Emacs last cut.
+Suitable for `interprogram-paste-function'."
+  (let ((reply (clipto--request "PASTE")))
+    (when reply
+      (let ((text (decode-coding-string reply 'utf-8)))
+        (unless (equal text clipto--last-cut)
+          text)))))
+
+(setq interprogram-cut-function #'clipto-cut
+      interprogram-paste-function #'clipto-paste)
+"#;
+
+/// Lua for Neovim's `g:clipboard`, backing `+` and `*` with
+/// `clipto nvim copy|paste`. Linewise yanks are stored with a trailing
+/// newline, and clipboard text ending in one pastes linewise, so the register
+/// type survives the round trip (and matches what tmux or a shell produce).
+const NVIM: &str = r#"-- clipto.lua --- generated by `clipto integrate nvim`
+--
+-- Put this file in ~/.config/nvim/plugin/ (loaded automatically), or
+-- require() it from init.lua.
+
+local function copy(lines, regtype)
+  local text = table.concat(lines, '\n')
+  if regtype == 'V' then
+    text = text .. '\n'
+  end
+  vim.fn.system({ 'clipto', 'nvim', 'copy' }, text)
+  if vim.v.shell_error ~= 0 then
+    vim.notify('clipto: copy failed', vim.log.levels.WARN)
+  end
+end
+
+local function paste()
+  local text = vim.fn.system({ 'clipto', 'nvim', 'paste' })
+  local lines = vim.split(text, '\n', { plain = true })
+  if #lines > 1 and lines[#lines] == '' then
+    table.remove(lines)
+    return { lines, 'V' }
+  end
+  return { lines, 'v' }
+end
+
+vim.g.clipboard = {
+  name = 'clipto',
+  copy = { ['+'] = copy, ['*'] = copy },
+  paste = { ['+'] = paste, ['*'] = paste },
+  cache_enabled = false,
+}
+"#;
+
+pub fn nvim() -> &'static str {
+    NVIM
+}
+
+pub fn emacs(socket: &Path) -> String {
+    // Inside an elisp string literal, only `\` and `"` are special.
+    let socket = socket.display().to_string().replace('\\', "\\\\").replace('"', "\\\"");
+    EMACS.replace("@SOCKET@", &socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emacs_socket_path_is_escaped() {
+        let elisp = emacs(Path::new("/run/a\\b\"c/line.sock"));
+        assert!(elisp.contains(r#"(defvar clipto-socket "/run/a\\b\"c/line.sock""#));
+    }
+}
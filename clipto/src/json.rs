@@ -0,0 +1,144 @@
+//! `--json` output for scripts (status bars, launchers). These structures are
+//! the stable interface, kept apart from the IPC types so the wire format can
+//! change without breaking them: fields may be added, but existing ones keep
+//! their name and meaning.
+
+use std::io::{self, Write};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use clipto_ipc::{EntryInfo, Endpoint, HistoryEntry, WatcherState};
+
+use crate::{age, format_time};
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    index: u32,
+    /// `user` or `wayland` (the desktop clipboard).
+    source: String,
+    client: &'a str,
+    stored_unix: u64,
+    /// `stored_unix` as RFC 3339.
+    time: String,
+    mime: Option<&'a str>,
+    size: u64,
+    pinned: bool,
+    /// Destroyed after its first paste; `preview` says only its size.
+    once: bool,
+    preview: &'a str,
+}
+
+#[derive(Serialize)]
+struct Info<'a> {
+    index: u32,
+    source: String,
+    client: &'a str,
+    stored_unix: u64,
+    time: String,
+    /// Seconds since `stored_unix`.
+    age_secs: u64,
+    mime: Option<&'a str>,
+    /// `text`, `url`, `binary` or a sniffed image type.
+    content: &'a str,
+    size: u64,
+    /// Null unless the entry is UTF-8 text.
+    lines: Option<u64>,
+    /// Null for paste-once entries.
+    sha256: Option<&'a str>,
+    pinned: bool,
+    once: bool,
+}
+
+#[derive(Serialize)]
+struct Types<'a> {
+    index: u32,
+    /// Given at copy time; null when `types` come from the content.
+    mime: Option<&'a str>,
+    /// Best first.
+    types: &'a [&'a str],
+}
+
+#[derive(Serialize)]
+struct Status<'a> {
+    socket: String,
+    backend: &'a str,
+    display: Option<&'a str>,
+    watcher: Watcher<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum Watcher<'a> {
+    Running { pid: u32, since_unix: u64, restarts: u32 },
+    Restarting { restarts: u32, last_exit: &'a str, retry_unix: u64 },
+    Stopped,
+}
+
+/// History or search results, as one array.
+pub fn entries(entries: &[HistoryEntry]) -> Result<()> {
+    let entries: Vec<Entry> = entries
+        .iter()
+        .map(|e| Entry {
+            index: e.index,
+            source: e.source.to_string(),
+            client: &e.client,
+            stored_unix: e.stored_unix,
+            time: format_time(e.stored_unix),
+            mime: e.mime.as_deref(),
+            size: e.size,
+            pinned: e.pinned,
+            once: e.once,
+            preview: &e.preview,
+        })
+        .collect();
+    print(&entries)
+}
+
+pub fn info(info: &EntryInfo) -> Result<()> {
+    print(&Info {
+        index: info.index,
+        source: info.source.to_string(),
+        client: &info.client,
+        stored_unix: info.stored_unix,
+        time: format_time(info.stored_unix),
+        age_secs: age(info.stored_unix).as_secs(),
+        mime: info.mime.as_deref(),
+        content: &info.content,
+        size: info.size,
+        lines: info.lines,
+        sha256: info.sha256.as_deref(),
+        pinned: info.pinned,
+        once: info.once,
+    })
+}
+
+pub fn types(info: &EntryInfo, types: &[&str]) -> Result<()> {
+    print(&Types { index: info.index, mime: info.mime.as_deref(), types })
+}
+
+pub fn status(socket: &Endpoint, status: &clipto_ipc::Status) -> Result<()> {
+    let watcher = match &status.watcher {
+        WatcherState::Running { pid, since_unix, restarts } => {
+            Watcher::Running { pid: *pid, since_unix: *since_unix, restarts: *restarts }
+        }
+        WatcherState::Restarting { restarts, last_exit, retry_unix } => Watcher::Restarting {
+            restarts: *restarts,
+            last_exit,
+            retry_unix: *retry_unix,
+        },
+        WatcherState::Stopped => Watcher::Stopped,
+    };
+    print(&Status {
+        socket: socket.to_string(),
+        backend: &status.backend,
+        display: status.display.as_deref(),
+        watcher,
+    })
+}
+
+fn print(value: &impl Serialize) -> Result<()> {
+    let mut out = io::stdout().lock();
+    serde_json::to_writer(&mut out, value).context("failed to write to stdout")?;
+    writeln!(out).context("failed to write to stdout")
+}
@@ -1,10 +1,34 @@
-use std::io::{self, Read, Write};
-use std::os::unix::net::UnixStream;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand, ValueEnum};
+use anyhow::{bail, Context, Result};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 
-use clipto_ipc::{CopySource, Request, Response};
+use clipto_client::Client;
+use clipto_ipc::{
+    display_char, CopySource, EntryInfo, HistoryEntry, Request, Response, Sanitize, SearchMode,
+    Session, WatcherState,
+};
+
+mod archive;
+mod daemon;
+mod edit;
+mod fallback;
+mod forward;
+mod integrate;
+mod json;
+mod nvim;
+mod pick;
+mod qr;
+mod sanitize;
+mod setup;
+mod share;
+mod shell;
+mod wayland;
 
 // ─── CLI definition ───────────────────────────────────────────────────────────
 
@@ -17,27 +41,326 @@ use clipto_ipc::{CopySource, Request, Response};
 struct Cli {
     #[command(subcommand)]
     command: Cmd,
+    /// Start clipd in the background first if it isn't running.
+    #[arg(long, global = true)]
+    spawn_daemon: bool,
 }
 
 #[derive(Subcommand)]
 enum Cmd {
-    /// Read stdin and send it to the clipboard daemon.
+    /// Send FILEs (concatenated) or stdin to the clipboard daemon.
     Copy {
+        /// Files to copy. Reads stdin when none are given.
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+        /// Same as a FILE argument. Repeatable.
+        #[arg(long = "file", value_name = "FILE")]
+        file: Vec<PathBuf>,
+        /// Content type to offer GUI apps, e.g. `image/png`.
+        #[arg(long = "type", value_name = "MIME")]
+        mime: Option<String>,
+        /// Copy the files themselves rather than their content: a
+        /// `text/uri-list` of file:// URIs that file managers can paste.
+        #[arg(long, conflicts_with = "mime")]
+        uri_list: bool,
         /// Where this copy originated. Use `wayland` only when called from
         /// `wl-paste --watch` to avoid a sync loop.
         #[arg(long, default_value = "user")]
         source: Source,
+        /// Store in register NAME of the current session instead of the
+        /// clipboard. Registers skip the history and the compositor and are
+        /// wiped when the session ends.
+        #[arg(long, value_name = "NAME")]
+        register: Option<String>,
+        /// Destroy the entry after its first paste, from a terminal or a GUI
+        /// app, e.g. to hand a password to exactly one application. `history`
+        /// withholds its preview, and search and export skip it.
+        #[arg(long, conflicts_with = "register")]
+        once: bool,
+        #[command(flatten)]
+        session: SessionArgs,
+        /// Copy into namespace NAME instead, which keeps only its last copy,
+        /// away from the history and the compositor. The `[namespace]` config
+        /// table can pick one automatically.
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["register", "once"])]
+        namespace: Option<String>,
+        /// Copy to the clipboard even where the config picks a namespace.
+        #[arg(long, conflicts_with = "namespace")]
+        global: bool,
     },
     /// Fetch the current clipboard from the daemon and write it to stdout.
+    Paste {
+        /// Paste history entry N instead of the current clipboard (0).
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        index: u32,
+        /// Have the daemon write the clipboard to a temporary file (deleted
+        /// after a few minutes) and print its path instead.
+        #[arg(long)]
+        as_file: bool,
+        /// Write the clipboard to FILE (mode 600) instead of stdout. The file
+        /// is replaced atomically and never left half-written.
+        #[arg(long, short = 'o', value_name = "FILE", conflicts_with = "as_file")]
+        output: Option<PathBuf>,
+        /// Show the clipboard as a QR code in the terminal, e.g. to move a URL
+        /// to a phone. Fails for payloads over about 2.9 KB.
+        #[arg(long, conflicts_with_all = ["as_file", "output"])]
+        qr: bool,
+        /// Strip terminal escape sequences and control characters other than
+        /// newline and tab. By default this happens only when stdout is a
+        /// terminal; the `[paste] sanitize` config key changes that.
+        #[arg(long, conflicts_with_all = ["as_file", "output", "qr"])]
+        sanitize: bool,
+        /// Never sanitize, even when stdout is a terminal.
+        #[arg(long, conflicts_with = "sanitize")]
+        no_sanitize: bool,
+        /// Paste register NAME of the current session.
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["index", "as_file"])]
+        register: Option<String>,
+        /// Session the register belongs to [default: tmux:$TMUX_PANE].
+        #[arg(long, value_name = "ID")]
+        session: Option<String>,
+        /// Paste namespace NAME, or the clipboard when it is empty.
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["register", "index", "as_file"])]
+        namespace: Option<String>,
+        /// Paste the clipboard even where the config picks a namespace.
+        #[arg(long, conflicts_with = "namespace")]
+        global: bool,
+        /// If clipd isn't running, read the desktop clipboard with wl-paste,
+        /// xclip or an OSC 52 terminal query instead of failing. The
+        /// `[paste] fallback` config key turns this on by default.
+        #[arg(long)]
+        fallback: bool,
+    },
+    /// List history entries, newest first, one per line.
+    History {
+        /// Line template. Placeholders: {index}, {source}, {client}, {time},
+        /// {mime}, {size}, {pinned}, {preview}.
+        /// `\t` and `\n` are expanded.
+        #[arg(long, default_value = "{index}\\t{preview}")]
+        format: String,
+        /// Print a JSON array of entries instead.
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+    },
+    /// List history entries whose content matches QUERY, newest first. The
+    /// daemon searches; entries never leave it except as previews.
+    Search {
+        query: String,
+        /// Treat QUERY as a regular expression instead of a substring.
+        #[arg(long, short = 'e')]
+        regex: bool,
+        /// Match case-insensitively.
+        #[arg(long, short = 'i')]
+        ignore_case: bool,
+        /// Line template, as for `history`.
+        #[arg(long, default_value = "{index}\\t{preview}")]
+        format: String,
+        /// Print a JSON array of entries instead.
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+    },
+    /// Edit the clipboard in $VISUAL or $EDITOR and copy the result back. The
+    /// content is held in memory (memfd), never in a file on disk.
+    Edit {
+        /// Edit history entry N instead of the current clipboard (0).
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        index: u32,
+    },
+    /// Make history entry N the current clipboard again.
+    Promote {
+        index: u32,
+    },
+    /// Pin history entry N so it is never evicted and is listed first.
+    Pin {
+        index: u32,
+    },
+    /// Unpin history entry N.
+    Unpin {
+        index: u32,
+    },
+    /// Save the history to an age-encrypted file, for `clipto import` on
+    /// another machine. Asks for a passphrase unless --recipient is given.
+    Export {
+        #[arg(long, short = 'o', value_name = "FILE")]
+        output: PathBuf,
+        /// Encrypt to this age public key instead of a passphrase.
+        /// Repeatable.
+        #[arg(long, short = 'r', value_name = "AGE_KEY")]
+        recipient: Vec<String>,
+    },
+    /// Add the entries of an exported file to the history, pinned entries
+    /// staying pinned.
+    Import {
+        file: PathBuf,
+        /// age identity file, for files exported with --recipient.
+        /// Repeatable.
+        #[arg(long, short = 'i', value_name = "FILE")]
+        identity: Vec<PathBuf>,
+    },
+    /// Encrypt the clipboard to someone else's key and print it as ASCII
+    /// armor, for `clipto receive` on their machine.
+    Share {
+        /// Share history entry N instead of the current clipboard (0).
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        index: u32,
+        /// age public key (`age1…`), or a GPG key ID or email to encrypt
+        /// with gpg. Repeatable.
+        #[arg(long, short = 'r', value_name = "KEY", required = true)]
+        recipient: Vec<String>,
+        /// Write to FILE instead of stdout.
+        #[arg(long, short = 'o', value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Decrypt what `clipto share` printed, from FILE or stdin, and copy it.
+    Receive {
+        file: Option<PathBuf>,
+        /// age identity file. Repeatable; GPG messages use gpg's keyring.
+        #[arg(long, short = 'i', value_name = "FILE")]
+        identity: Vec<PathBuf>,
+    },
+    /// Empty the clipboard and history, including the compositor's.
+    Clear,
+    /// Print daemon counters (copies, pastes, syncs, errors).
+    Stats,
+    /// Print the daemon's most recent warnings and errors, oldest first.
+    Errors,
+    /// Print the socket, which desktop clipboard the daemon syncs with, and
+    /// whether its watcher is running.
+    Status {
+        /// Print a JSON object instead.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Describe the clipboard without printing it: size, content type, line
+    /// count, SHA-256 and age.
+    Info {
+        /// Describe history entry N instead of the current clipboard (0).
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        index: u32,
+        /// Print a JSON object instead.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the clipboard's content type, one per line: the one given at
+    /// copy time, else what the daemon makes of the content.
+    Types {
+        /// List the types of history entry N instead of the current clipboard
+        /// (0).
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        index: u32,
+        /// Print a JSON object instead.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Fuzzy-search the history in a terminal UI; Enter makes the selected
+    /// entry the current clipboard.
+    Pick,
+    /// Copy the compositor clipboard into the daemon, choosing among the
+    /// offered MIME types. Run by clipd's `wl-paste --watch`.
+    #[command(hide = true)]
+    WaylandSync {
+        /// Preferred type patterns, best first.
+        #[arg(long)]
+        prefer: Vec<String>,
+        /// Type patterns never fetched unless preferred.
+        #[arg(long)]
+        deny: Vec<String>,
+    },
+    /// Guided first run: seal a key, install the systemd service, add tmux and
+    /// editor bindings, then test copy and paste end to end.
+    Setup {
+        /// Accept every default instead of asking.
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Neovim clipboard provider commands, used by the config from
+    /// `clipto integrate nvim`.
+    Nvim {
+        #[command(subcommand)]
+        action: NvimAction,
+    },
+    /// Open an SSH session to HOST with clipd's socket forwarded, so
+    /// `clipto` there uses this machine's clipboard.
+    Forward {
+        host: String,
+        /// Socket path on HOST [default: /tmp/clipto-$USER.sock].
+        #[arg(long, value_name = "PATH")]
+        remote_socket: Option<String>,
+        /// Print the ~/.ssh/config equivalent instead of connecting.
+        #[arg(long)]
+        print: bool,
+        /// Extra ssh options, after `--`.
+        #[arg(last = true)]
+        ssh_args: Vec<String>,
+    },
+    /// Print a config snippet wiring an editor to clipd.
+    Integrate {
+        #[arg(value_enum)]
+        target: Integration,
+    },
+    /// Print completions and line-editor widgets for SHELL; add
+    /// `eval "$(clipto shell-init SHELL)"` to its rc file.
+    ShellInit {
+        #[arg(value_enum)]
+        shell: InitShell,
+    },
+    /// Run the clipboard daemon, detached unless --foreground.
+    Daemon {
+        /// Stay attached, logging to stderr (or journald under systemd).
+        #[arg(long)]
+        foreground: bool,
+    },
+}
+
+#[derive(Args)]
+struct SessionArgs {
+    /// Session the register belongs to [default: tmux:$TMUX_PANE].
+    #[arg(long, value_name = "ID")]
+    session: Option<String>,
+    /// Process whose exit ends the session, if this copy opens it [default:
+    /// the tmux pane's shell, else the parent process].
+    #[arg(long, value_name = "PID")]
+    session_pid: Option<u32>,
+}
+
+#[derive(Subcommand)]
+enum NvimAction {
+    /// Store stdin; for `g:clipboard.copy`.
+    Copy,
+    /// Print the clipboard if it is text, nothing otherwise; for
+    /// `g:clipboard.paste`.
     Paste,
 }
 
+#[derive(ValueEnum, Clone)]
+enum Integration {
+    /// Elisp for `interprogram-cut-function` / `interprogram-paste-function`
+    /// over the line socket.
+    Emacs,
+    /// Lua setting `g:clipboard` so `+` and `*` use clipd.
+    Nvim,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
 #[derive(ValueEnum, Clone)]
 enum Source {
     User,
     Wayland,
 }
 
+/// Where `clipto copy` stores its payload.
+enum Target {
+    Clipboard,
+    Register(Session, String),
+    Namespace(String),
+}
+
 impl From<Source> for CopySource {
     fn from(s: Source) -> Self {
         match s {
@@ -49,31 +372,301 @@ impl From<Source> for CopySource {
 
 // ─── socket helpers ───────────────────────────────────────────────────────────
 
-fn connect() -> Result<UnixStream> {
-    let path = clipto_ipc::socket_path()?;
-    UnixStream::connect(&path)
-        .with_context(|| format!("failed to connect to clipd at {} — is clipd running?", path.display()))
+/// Set by `--spawn-daemon`.
+static SPAWN_DAEMON: AtomicBool = AtomicBool::new(false);
+
+/// The daemon at the configured socket, which `--spawn-daemon` starts if
+/// it isn't running.
+fn client() -> Result<Client> {
+    let client = Client::new()?;
+    if SPAWN_DAEMON.load(Ordering::Relaxed) {
+        return Ok(client.with_starter(daemon::spawn));
+    }
+    Ok(client)
+}
+
+/// Send one request and read its response.
+fn request(request: &Request) -> Result<Response> {
+    client()?.request(request)
+}
+
+/// Whether `err`, from a request, means nothing answered on the socket.
+fn daemon_down(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<io::Error>().is_some_and(clipto_client::not_running)
+}
+
+/// The register session named on the command line, or the tmux pane.
+fn session_id(explicit: Option<String>) -> Result<String> {
+    if let Some(id) = explicit {
+        return Ok(id);
+    }
+    match std::env::var("TMUX_PANE") {
+        Ok(pane) => Ok(format!("tmux:{pane}")),
+        Err(_) => bail!("no session: pass --session or run inside tmux"),
+    }
+}
+
+fn session(args: SessionArgs) -> Result<Session> {
+    let id = session_id(args.session)?;
+    let pid = match args.session_pid {
+        Some(pid) => pid,
+        // Key bindings run clipto from a short-lived `sh -c`; the pane's
+        // shell is what should own the session.
+        None if id.starts_with("tmux:") => tmux_pane_pid()?,
+        None => std::os::unix::process::parent_id(),
+    };
+    Ok(Session { id, pid })
+}
+
+/// The namespace a plain copy or paste uses: `--namespace`, else the tmux
+/// session's if the `[namespace]` config asks for it.
+fn namespace(explicit: Option<String>, global: bool) -> Result<Option<String>> {
+    if global || explicit.is_some() {
+        return Ok(explicit);
+    }
+    if !clipto_ipc::namespace_config()?.tmux || std::env::var_os("TMUX").is_none() {
+        return Ok(None);
+    }
+    let out = std::process::Command::new("tmux")
+        .args(["display-message", "-p", "#{session_name}"])
+        .output()
+        .context("failed to run tmux")?;
+    if !out.status.success() {
+        bail!("tmux display-message exited with {}", out.status);
+    }
+    Ok(Some(format!("tmux:{}", String::from_utf8_lossy(&out.stdout).trim())))
+}
+
+fn tmux_pane_pid() -> Result<u32> {
+    let out = std::process::Command::new("tmux")
+        .args(["display-message", "-p", "#{pane_pid}"])
+        .output()
+        .context("failed to run tmux")?;
+    if !out.status.success() {
+        bail!("tmux display-message exited with {}", out.status);
+    }
+    String::from_utf8_lossy(&out.stdout).trim().parse().context("unexpected pane_pid from tmux")
+}
+
+/// Print one line per history entry, filling in `format`'s placeholders.
+/// Client-supplied fields are made single-line and terminal-safe, like the
+/// daemon's previews.
+fn print_entries(entries: &[HistoryEntry], format: &str) -> Result<()> {
+    let template = unescape(format);
+    let mut out = io::stdout().lock();
+    for e in entries {
+        let line = fill(&template, |name| {
+            Some(match name {
+                "index" => e.index.to_string(),
+                "source" => e.source.to_string(),
+                "client" => e.client.chars().map(display_char).collect(),
+                "time" => format_time(e.stored_unix),
+                "mime" => e.mime.as_deref().unwrap_or("").chars().map(display_char).collect(),
+                "size" => e.size.to_string(),
+                "pinned" => if e.pinned { "pinned" } else { "" }.to_owned(),
+                "preview" => e.preview.clone(),
+                _ => return None,
+            })
+        });
+        writeln!(out, "{line}").context("failed to write to stdout")?;
+    }
+    Ok(())
+}
+
+/// Replace each `{name}` in `template` for which `field` has a value, in a
+/// single pass: substituted text is never scanned again, and unknown
+/// placeholders are kept as written.
+fn fill(template: &str, field: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| Some((end, field(&rest[1..end])?)));
+        match value {
+            Some((end, value)) => {
+                out.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Whether `clipto paste` cleans what it writes to stdout: as forced on the
+/// command line, else as the config says.
+fn should_sanitize(force: bool, never: bool) -> Result<bool> {
+    if force || never {
+        return Ok(force);
+    }
+    Ok(match clipto_ipc::paste_config()?.sanitize {
+        Sanitize::Tty => io::stdout().is_terminal(),
+        Sanitize::Always => true,
+        Sanitize::Never => false,
+    })
+}
+
+/// Time since `unix_time`, to the second.
+fn age(unix_time: u64) -> Duration {
+    let now = UNIX_EPOCH.elapsed().unwrap_or_default().as_secs();
+    Duration::from_secs(now.saturating_sub(unix_time))
+}
+
+/// RFC 3339 UTC timestamp, to the second.
+fn format_time(unix_time: u64) -> String {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(unix_time)).to_string()
+}
+
+/// The types `info`'s entry can be pasted as, best first: the one given at
+/// copy time, else the daemon's reading of the content.
+fn types(info: &EntryInfo) -> Vec<&str> {
+    if let Some(mime) = &info.mime {
+        return vec![mime];
+    }
+    match info.content.as_str() {
+        "text" | "url" => vec!["text/plain;charset=utf-8", "text/plain"],
+        "binary" => vec!["application/octet-stream"],
+        image => vec![image],
+    }
+}
+
+/// `file://` URI of `path`, made absolute, as file managers expect in a
+/// `text/uri-list`. Bytes outside the unreserved set are percent-encoded.
+fn file_uri(path: &Path) -> Result<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = fs::canonicalize(path)
+        .with_context(|| format!("failed to resolve {}", path.display()))?;
+    let mut uri = String::from("file://");
+    for &b in path.as_os_str().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{b:02X}")),
+        }
+    }
+    Ok(uri)
+}
+
+/// Write `data` to a mode-600 temporary file next to `path`, then rename it
+/// into place, so `path` never holds a partial write.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let name = path.file_name().context("output path has no file name")?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = path.with_file_name(tmp_name);
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    let result = (|| -> io::Result<()> {
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Expand the `\t`, `\n`, and `\\` escapes a shell leaves in single-quoted
+/// arguments.
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
 }
 
 // ─── main ─────────────────────────────────────────────────────────────────────
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    SPAWN_DAEMON.store(cli.spawn_daemon, Ordering::Relaxed);
 
     match cli.command {
-        Cmd::Copy { source } => {
-            let mut payload = Vec::new();
-            io::stdin()
-                .read_to_end(&mut payload)
-                .context("failed to read stdin")?;
-
-            let mut stream = connect()?;
-            clipto_ipc::write_frame(
-                &mut stream,
-                &Request::Copy { payload, source: source.into() },
-            )?;
-
-            match clipto_ipc::read_frame::<Response>(&mut stream)? {
+        Cmd::Copy {
+            files,
+            file,
+            mime,
+            uri_list,
+            source,
+            register,
+            once,
+            session: session_args,
+            namespace: explicit,
+            global,
+        } => {
+            // Resolve the target first so a usage error doesn't eat stdin.
+            let target = match register {
+                Some(name) => Target::Register(session(session_args)?, name),
+                None if once || matches!(source, Source::Wayland) => Target::Clipboard,
+                None => match namespace(explicit, global)? {
+                    Some(namespace) => Target::Namespace(namespace),
+                    None => Target::Clipboard,
+                },
+            };
+            let files: Vec<PathBuf> = files.into_iter().chain(file).collect();
+
+            let (payload, mime) = if uri_list {
+                if files.is_empty() {
+                    bail!("--uri-list needs at least one FILE");
+                }
+                let mut list = String::new();
+                for path in &files {
+                    list.push_str(&file_uri(path)?);
+                    list.push_str("\r\n");
+                }
+                (list.into_bytes(), Some("text/uri-list".to_owned()))
+            } else if files.is_empty() {
+                let mut payload = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut payload)
+                    .context("failed to read stdin")?;
+                (payload, mime)
+            } else {
+                let mut payload = Vec::new();
+                for path in &files {
+                    let mut f = fs::File::open(path)
+                        .with_context(|| format!("failed to open {}", path.display()))?;
+                    f.read_to_end(&mut payload)
+                        .with_context(|| format!("failed to read {}", path.display()))?;
+                }
+                (payload, mime)
+            };
+
+            let req = match target {
+                Target::Register(session, name) => Request::SetRegister { session, name, payload },
+                Target::Namespace(namespace) => Request::SetNamespace { namespace, payload, mime },
+                Target::Clipboard => Request::Copy { payload, source: source.into(), mime, once },
+            };
+            match request(&req)? {
                 Response::Ok => {}
                 Response::Error { message } => {
                     eprintln!("clipd: {message}");
@@ -86,16 +679,79 @@ fn main() -> Result<()> {
             }
         }
 
-        Cmd::Paste => {
-            let mut stream = connect()?;
-            clipto_ipc::write_frame(&mut stream, &Request::Paste)?;
+        Cmd::WaylandSync { prefer, deny } => {
+            let Some((mime, payload)) = wayland::fetch(&prefer, &deny)? else {
+                return Ok(());
+            };
+            let req = Request::Copy {
+                payload,
+                source: CopySource::Wayland,
+                mime: Some(mime),
+                once: false,
+            };
+            match request(&req)? {
+                Response::Ok => {}
+                Response::Error { message } => {
+                    eprintln!("clipd: {message}");
+                    std::process::exit(1);
+                }
+                _ => {
+                    eprintln!("clipd: unexpected response to Copy");
+                    std::process::exit(1);
+                }
+            }
+        }
 
-            match clipto_ipc::read_frame::<Response>(&mut stream)? {
-                Response::Payload { data } => {
-                    io::stdout()
-                        .write_all(&data)
-                        .context("failed to write to stdout")?;
+        Cmd::Paste {
+            index,
+            as_file,
+            output,
+            qr,
+            sanitize,
+            no_sanitize,
+            register,
+            session,
+            namespace: explicit,
+            global,
+            fallback,
+        } => {
+            let req = match (register, as_file, index) {
+                (Some(name), _, _) => Request::GetRegister { session: session_id(session)?, name },
+                (None, true, index) => Request::PasteFile { index },
+                (None, false, 0) => match namespace(explicit, global)? {
+                    Some(namespace) => Request::GetNamespace { namespace },
+                    None => Request::Paste,
+                },
+                (None, false, index) => Request::Entry { index },
+            };
+            // The fallbacks only know the desktop clipboard.
+            let fallback = matches!(req, Request::Paste | Request::GetNamespace { .. })
+                && (fallback || clipto_ipc::paste_config()?.fallback);
+            let response = match request(&req) {
+                Err(e) if fallback && daemon_down(&e) => {
+                    let (data, source) = fallback::paste().context(e)?;
+                    eprintln!("clipto: clipd is not running; pasted from {source}");
+                    Response::Payload { data }
                 }
+                response => response?,
+            };
+            match response {
+                Response::Payload { data } if qr => println!("{}", qr::render(&data)?),
+                Response::Payload { data } => match output {
+                    Some(path) => write_atomic(&path, &data)?,
+                    None if should_sanitize(sanitize, no_sanitize)? => {
+                        let (data, removed) = sanitize::sanitize(&data);
+                        if removed > 0 {
+                            let s = if removed == 1 { "" } else { "s" };
+                            eprintln!("clipto: removed {removed} control sequence{s}");
+                        }
+                        io::stdout().write_all(&data).context("failed to write to stdout")?
+                    }
+                    None => io::stdout()
+                        .write_all(&data)
+                        .context("failed to write to stdout")?,
+                },
+                Response::File { path } => println!("{}", path.display()),
                 Response::Error { message } => {
                     eprintln!("clipd: {message}");
                     std::process::exit(1);
@@ -106,7 +762,265 @@ fn main() -> Result<()> {
                 }
             }
         }
+
+        Cmd::History { format, json } => match request(&Request::History)? {
+            Response::History(entries) if json => json::entries(&entries)?,
+            Response::History(entries) => print_entries(&entries, &format)?,
+            Response::Error { message } => {
+                eprintln!("clipd: {message}");
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("clipd: unexpected response to History");
+                std::process::exit(1);
+            }
+        },
+
+        Cmd::Search { query, regex, ignore_case, format, json } => {
+            let mode = if regex { SearchMode::Regex } else { SearchMode::Substring };
+            match request(&Request::Search { query, mode, ignore_case })? {
+                Response::History(entries) => {
+                    if json {
+                        json::entries(&entries)?;
+                    } else {
+                        print_entries(&entries, &format)?;
+                    }
+                    if entries.is_empty() {
+                        std::process::exit(1);
+                    }
+                }
+                Response::Error { message } => {
+                    eprintln!("clipd: {message}");
+                    std::process::exit(1);
+                }
+                _ => {
+                    eprintln!("clipd: unexpected response to Search");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Cmd::Promote { index } => match request(&Request::Promote { index })? {
+            Response::Ok => {}
+            Response::Error { message } => {
+                eprintln!("clipd: {message}");
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("clipd: unexpected response to Promote");
+                std::process::exit(1);
+            }
+        },
+
+        Cmd::Pin { index } | Cmd::Unpin { index } => {
+            let pinned = matches!(cli.command, Cmd::Pin { .. });
+            match request(&Request::Pin { index, pinned })? {
+                Response::Ok => {}
+                Response::Error { message } => {
+                    eprintln!("clipd: {message}");
+                    std::process::exit(1);
+                }
+                _ => {
+                    eprintln!("clipd: unexpected response to Pin");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Cmd::Export { output, recipient } => {
+            let count = archive::export(&output, &recipient)?;
+            eprintln!("exported {count} entries to {}", output.display());
+        }
+
+        Cmd::Import { file, identity } => {
+            let count = archive::import(&file, &identity)?;
+            eprintln!("imported {count} entries");
+        }
+
+        Cmd::Share { index, recipient, output } => {
+            share::share(index, &recipient, output.as_deref())?
+        }
+
+        Cmd::Receive { file, identity } => share::receive(file.as_deref(), &identity)?,
+
+        Cmd::Clear => match request(&Request::Clear)? {
+            Response::Ok => {}
+            Response::Error { message } => {
+                eprintln!("clipd: {message}");
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("clipd: unexpected response to Clear");
+                std::process::exit(1);
+            }
+        },
+
+        Cmd::Stats => match request(&Request::Stats)? {
+            Response::Stats(stats) => {
+                println!("uptime_secs: {}", stats.uptime_secs);
+                println!("copies: {}", stats.copies);
+                println!("pastes: {}", stats.pastes);
+                println!("bytes_stored: {}", stats.bytes_stored);
+                println!("wayland_syncs: {}", stats.wayland_syncs);
+                println!("errors: {}", stats.errors);
+                println!("rejected_payloads: {}", stats.rejected_payloads);
+                println!("throttled: {}", stats.throttled);
+            }
+            Response::Error { message } => {
+                eprintln!("clipd: {message}");
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("clipd: unexpected response to Stats");
+                std::process::exit(1);
+            }
+        },
+
+        Cmd::Errors => match request(&Request::Errors)? {
+            Response::Errors(records) => {
+                for r in records {
+                    println!(
+                        "{} {:>5} {}: {}",
+                        format_time(r.unix_time),
+                        r.level,
+                        r.target,
+                        r.message
+                    );
+                }
+            }
+            Response::Error { message } => {
+                eprintln!("clipd: {message}");
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("clipd: unexpected response to Errors");
+                std::process::exit(1);
+            }
+        },
+
+        Cmd::Status { json } => match request(&Request::Status)? {
+            Response::Status(status) if json => json::status(&clipto_ipc::socket()?, &status)?,
+            Response::Status(status) => {
+                println!("socket: {}", clipto_ipc::socket()?);
+                println!("backend: {}", status.backend);
+                if let Some(display) = status.display {
+                    println!("display: {display}");
+                }
+                match status.watcher {
+                    WatcherState::Running { pid, since_unix, restarts } => {
+                        println!("watcher: running (pid {pid})");
+                        println!("watcher_since: {}", format_time(since_unix));
+                        println!("watcher_restarts: {restarts}");
+                    }
+                    WatcherState::Restarting { restarts, last_exit, retry_unix } => {
+                        println!("watcher: restarting at {}", format_time(retry_unix));
+                        println!("watcher_last_exit: {last_exit}");
+                        println!("watcher_restarts: {restarts}");
+                    }
+                    WatcherState::Stopped => println!("watcher: stopped"),
+                }
+            }
+            Response::Error { message } => {
+                eprintln!("clipd: {message}");
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("clipd: unexpected response to Status");
+                std::process::exit(1);
+            }
+        },
+
+        Cmd::Info { index, json } => match request(&Request::Info { index })? {
+            Response::Info(info) if json => json::info(&info)?,
+            Response::Info(info) => {
+                println!("index: {}", info.index);
+                println!("content: {}", info.content);
+                if let Some(mime) = &info.mime {
+                    println!("mime: {mime}");
+                }
+                println!("size: {} bytes", info.size);
+                if let Some(lines) = info.lines {
+                    println!("lines: {lines}");
+                }
+                match &info.sha256 {
+                    Some(sha256) => println!("sha256: {sha256}"),
+                    None => println!("sha256: withheld (paste once)"),
+                }
+                println!("stored: {}", format_time(info.stored_unix));
+                println!("age: {}", humantime::format_duration(age(info.stored_unix)));
+                println!("source: {}", info.source);
+                println!("client: {}", info.client);
+                println!("pinned: {}", if info.pinned { "yes" } else { "no" });
+                println!("once: {}", if info.once { "yes" } else { "no" });
+            }
+            Response::Error { message } => {
+                eprintln!("clipd: {message}");
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("clipd: unexpected response to Info");
+                std::process::exit(1);
+            }
+        },
+
+        Cmd::Types { index, json } => match request(&Request::Info { index })? {
+            Response::Info(info) if json => json::types(&info, &types(&info))?,
+            Response::Info(info) => {
+                for mime in types(&info) {
+                    println!("{mime}");
+                }
+            }
+            Response::Error { message } => {
+                eprintln!("clipd: {message}");
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("clipd: unexpected response to Info");
+                std::process::exit(1);
+            }
+        },
+
+        Cmd::Edit { index } => edit::run(index)?,
+
+        Cmd::Pick => pick::run()?,
+
+        Cmd::Nvim { action } => match action {
+            NvimAction::Copy => nvim::copy()?,
+            NvimAction::Paste => nvim::paste()?,
+        },
+
+        Cmd::Setup { yes } => setup::run(yes)?,
+
+        Cmd::Forward { host, remote_socket, print, ssh_args } => {
+            forward::run(&host, remote_socket, print, &ssh_args)?
+        }
+
+        Cmd::Daemon { foreground } => daemon::run(foreground)?,
+
+        Cmd::Integrate { target } => match target {
+            Integration::Emacs => print!("{}", integrate::emacs(&clipto_ipc::line_socket_path()?)),
+            Integration::Nvim => print!("{}", integrate::nvim()),
+        },
+
+        Cmd::ShellInit { shell } => shell::init(shell, &mut Cli::command())?,
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_substitutes_in_one_pass() {
+        let field = |name: &str| match name {
+            "a" => Some("{b}".to_owned()),
+            "b" => Some("B".to_owned()),
+            _ => None,
+        };
+        assert_eq!(fill("{a}\t{b}", field), "{b}\tB");
+        assert_eq!(fill("{x} {b} {", field), "{x} B {");
+        assert_eq!(fill("{{b}}", field), "{B}");
+    }
+}
@@ -0,0 +1,35 @@
+//! `clipto nvim copy|paste`: the command side of Neovim's clipboard provider
+//! (see `clipto integrate nvim`).
+//!
+//! Neovim reports any stderr output or non-zero exit as a clipboard error on
+//! every yank and put, so these stay quiet in normal situations: an empty
+//! clipboard pastes as nothing, and so does an entry that isn't text.
+
+use std::io::{self, Read, Write};
+
+use anyhow::{bail, Context, Result};
+
+use clipto_client::CopyOptions;
+use clipto_ipc::{Request, Response};
+
+use crate::{client, request};
+
+pub fn copy() -> Result<()> {
+    let mut payload = Vec::new();
+    io::stdin().read_to_end(&mut payload).context("failed to read stdin")?;
+    client()?.copy(payload, CopyOptions::default())
+}
+
+pub fn paste() -> Result<()> {
+    let data = match request(&Request::Paste)? {
+        Response::Payload { data } => data,
+        // Usually an empty clipboard; either way there is nothing to paste.
+        Response::Error { .. } => return Ok(()),
+        _ => bail!("clipd: unexpected response to Paste"),
+    };
+    // Images and other binary entries would land in the buffer as garbage.
+    if std::str::from_utf8(&data).is_ok() {
+        io::stdout().write_all(&data).context("failed to write to stdout")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! `clipto pick`: fuzzy history picker. Type to filter, arrows to move,
+//! Enter to make the selected entry the current clipboard, Esc to cancel.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use clipto_ipc::{HistoryEntry, Request, Response};
+
+use crate::{format_time, request};
+
+/// Bytes of the selected entry shown in the preview pane.
+const PREVIEW_BYTES: usize = 64 * 1024;
+
+pub fn run() -> Result<()> {
+    let entries = match request(&Request::History)? {
+        Response::History(entries) => entries,
+        Response::Error { message } => bail!("clipd: {message}"),
+        _ => bail!("clipd: unexpected response to History"),
+    };
+    if entries.is_empty() {
+        bail!("clipboard history is empty");
+    }
+
+    let mut terminal = ratatui::init();
+    let picked = Picker::new(entries).run(&mut terminal);
+    ratatui::restore();
+
+    if let Some(index) = picked? {
+        match request(&Request::Promote { index })? {
+            Response::Ok => {}
+            Response::Error { message } => bail!("clipd: {message}"),
+            _ => bail!("clipd: unexpected response to Promote"),
+        }
+    }
+    Ok(())
+}
+
+struct Picker {
+    entries: Vec<HistoryEntry>,
+    query: String,
+    /// Positions into `entries` matching `query`, best first.
+    matches: Vec<usize>,
+    list: ListState,
+    /// Full text of entries already fetched for the preview pane.
+    previews: HashMap<u32, String>,
+    matcher: SkimMatcherV2,
+}
+
+impl Picker {
+    fn new(entries: Vec<HistoryEntry>) -> Self {
+        let mut picker = Self {
+            entries,
+            query: String::new(),
+            matches: Vec::new(),
+            list: ListState::default(),
+            previews: HashMap::new(),
+            matcher: SkimMatcherV2::default(),
+        };
+        picker.refilter();
+        picker
+    }
+
+    /// Returns the daemon index of the chosen entry, or `None` if cancelled.
+    fn run(mut self, terminal: &mut DefaultTerminal) -> Result<Option<u32>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Char('c') if ctrl => return Ok(None),
+                KeyCode::Enter => return Ok(self.selected().map(|e| e.index)),
+                KeyCode::Up => self.list.select_previous(),
+                KeyCode::Char('p') if ctrl => self.list.select_previous(),
+                KeyCode::Down => self.list.select_next(),
+                KeyCode::Char('n') if ctrl => self.list.select_next(),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.refilter();
+                }
+                KeyCode::Char(c) if !ctrl => {
+                    self.query.push(c);
+                    self.refilter();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn refilter(&mut self) {
+        if self.query.is_empty() {
+            self.matches = (0..self.entries.len()).collect();
+        } else {
+            let mut scored: Vec<(i64, usize)> = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter_map(|(i, e)| {
+                    self.matcher.fuzzy_match(&e.preview, &self.query).map(|score| (score, i))
+                })
+                .collect();
+            // Best score first; ties keep history order (newest first).
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+            self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        }
+        self.list.select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn selected(&self) -> Option<&HistoryEntry> {
+        let pos = self.list.selected()?;
+        self.matches.get(pos).map(|&i| &self.entries[i])
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search, body] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body);
+
+        let title = format!(" clipto pick — {}/{} ", self.matches.len(), self.entries.len());
+        frame.render_widget(
+            Paragraph::new(format!("> {}", self.query)).block(Block::bordered().title(title)),
+            search,
+        );
+
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|&i| {
+                let e = &self.entries[i];
+                let pin = if e.pinned { '*' } else { ' ' };
+                let line = format!("{:>3}{pin} {:<7} {}", e.index, e.source, e.preview);
+                ListItem::new(Line::from(line))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(" history "))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let (title, text) = match self.selected() {
+            Some(e) => {
+                let title = format!(" {} · {} ", e.client, format_time(e.stored_unix));
+                (title, self.preview(e.index))
+            }
+            None => (" preview ".to_owned(), String::new()),
+        };
+        frame.render_widget(
+            Paragraph::new(text)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(title)),
+            preview_area,
+        );
+    }
+
+    /// Fetch (once) and sanitize the start of entry `index` for display.
+    fn preview(&mut self, index: u32) -> String {
+        self.previews
+            .entry(index)
+            .or_insert_with(|| match request(&Request::Entry { index }) {
+                Ok(Response::Payload { data }) => render_preview(&data),
+                Ok(Response::Error { message }) => format!("[{message}]"),
+                Ok(_) => "[unexpected response]".to_owned(),
+                Err(e) => format!("[{e:#}]"),
+            })
+            .clone()
+    }
+}
+
+/// Text with control characters neutralized so the entry can't drive the
+/// terminal; binary payloads are summarized.
+fn render_preview(data: &[u8]) -> String {
+    let head = &data[..data.len().min(PREVIEW_BYTES)];
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // A multi-byte character may straddle the cut.
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap(),
+        Err(_) => return format!("[binary, {} bytes]", data.len()),
+    };
+
+    text.chars()
+        .map(|c| match c {
+            '\n' => "\n".to_owned(),
+            '\t' => "    ".to_owned(),
+            c if c.is_control() => "�".to_owned(),
+            c => c.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,48 @@
+//! `clipto paste --qr`: the clipboard as a QR code drawn with Unicode half
+//! blocks, for moving a URL or secret to a phone without the network.
+
+use anyhow::{bail, Result};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::types::QrError;
+use qrcode::{EcLevel, QrCode};
+
+/// Byte-mode capacity of the largest QR code (version 40) at level L.
+const MAX_BYTES: usize = 2953;
+
+/// Render `data` for a terminal. Uses medium error correction when the payload
+/// fits, and falls back to low before giving up.
+pub fn render(data: &[u8]) -> Result<String> {
+    let code = match QrCode::with_error_correction_level(data, EcLevel::M) {
+        Err(QrError::DataTooLong) => QrCode::with_error_correction_level(data, EcLevel::L),
+        other => other,
+    };
+    let code = match code {
+        Ok(code) => code,
+        Err(QrError::DataTooLong) => bail!(
+            "clipboard is too large for a QR code ({} bytes, at most {MAX_BYTES})",
+            data.len()
+        ),
+        Err(e) => bail!("can't encode a QR code: {e}"),
+    };
+
+    // Light modules drawn as blocks, so the code reads correctly on the
+    // usual dark terminal background.
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build();
+
+    // Modules plus the 4-module quiet zone on each side.
+    let width = code.width() + 8;
+    if let Ok((columns, _)) = ratatui::crossterm::terminal::size() {
+        if width > columns as usize {
+            eprintln!(
+                "warning: the QR code is {width} columns wide but the terminal has {columns}; \
+                 widen it or shrink the font before scanning"
+            );
+        }
+    }
+    Ok(image)
+}
@@ -0,0 +1,140 @@
+//! `clipto paste --sanitize`: strip what a terminal would act on rather than
+//! display, so text from a malicious "copy" button can't move the cursor,
+//! rewrite the title, end bracketed paste early and run a command, or hide
+//! part of itself.
+//!
+//! Escape sequences are dropped whole (not just their ESC, which would leave
+//! `[201~` behind), along with every other C0 and C1 control character except
+//! newline and tab. So are the bidi overrides and isolates (U+202A–U+202E,
+//! U+2066–U+2069), which reorder how the text around them is displayed, so
+//! a pasted command could run something other than what it showed. Invalid
+//! UTF-8 is replaced, since a terminal would decode it unpredictably.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// `data` with control sequences removed, and how many were.
+pub fn sanitize(data: &[u8]) -> (Vec<u8>, usize) {
+    let text = String::from_utf8_lossy(data);
+    let mut out = String::with_capacity(text.len());
+    let mut removed = 0;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' | '\t' => out.push(c),
+            '\x1b' => {
+                removed += 1;
+                skip_escape(&mut chars);
+            }
+            '\u{9b}' => {
+                removed += 1;
+                skip_csi(&mut chars);
+            }
+            // DCS, SOS, OSC, PM, APC: strings up to a terminator.
+            '\u{90}' | '\u{98}' | '\u{9d}' | '\u{9e}' | '\u{9f}' => {
+                removed += 1;
+                skip_string(&mut chars);
+            }
+            '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => removed += 1,
+            c if c.is_control() => removed += 1,
+            c => out.push(c),
+        }
+    }
+    (out.into_bytes(), removed)
+}
+
+/// The rest of a sequence that started with ESC.
+fn skip_escape(chars: &mut Peekable<Chars>) {
+    match chars.next() {
+        Some('[') => skip_csi(chars),
+        Some(']' | 'P' | 'X' | '^' | '_') => skip_string(chars),
+        // nF sequences: intermediates, then a final byte.
+        Some(' '..='/') => {
+            while chars.next_if(|c| matches!(c, ' '..='/')).is_some() {}
+            chars.next();
+        }
+        _ => {}
+    }
+}
+
+/// Parameter and intermediate bytes, then the final byte.
+fn skip_csi(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| matches!(c, ' '..='?')).is_some() {}
+    chars.next_if(|c| matches!(c, '@'..='~'));
+}
+
+/// Up to and including BEL or ST (`ESC \` or U+009C).
+fn skip_string(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\x07' | '\u{9c}' => return,
+            '\x1b' => {
+                chars.next_if_eq(&'\\');
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(data: &[u8]) -> (String, usize) {
+        let (out, removed) = sanitize(data);
+        (String::from_utf8(out).unwrap(), removed)
+    }
+
+    #[test]
+    fn plain_text_is_untouched() {
+        assert_eq!(clean(b"echo hi\n\tdone \xe2\x9c\x93"), ("echo hi\n\tdone ✓".to_owned(), 0));
+    }
+
+    #[test]
+    fn bracketed_paste_end_is_removed_whole() {
+        assert_eq!(clean(b"ls\x1b[201~rm -rf ~\n"), ("lsrm -rf ~\n".to_owned(), 1));
+    }
+
+    #[test]
+    fn csi_sequences_are_removed() {
+        assert_eq!(clean(b"\x1b[1;31mred\x1b[0m \x1b[2J"), ("red ".to_owned(), 3));
+        // The 8-bit CSI, U+009B.
+        assert_eq!(clean("\u{9b}2Ahidden".as_bytes()), ("hidden".to_owned(), 1));
+    }
+
+    #[test]
+    fn strings_are_removed_up_to_their_terminator() {
+        // OSC title ended by BEL, DCS ended by ST, APC ended by 8-bit ST.
+        assert_eq!(clean(b"a\x1b]0;pwned\x07b"), ("ab".to_owned(), 1));
+        assert_eq!(clean(b"a\x1bPq#0\x1b\\b"), ("ab".to_owned(), 1));
+        assert_eq!(clean("a\u{9f}x\u{9c}b".as_bytes()), ("ab".to_owned(), 1));
+    }
+
+    #[test]
+    fn unterminated_string_swallows_the_rest() {
+        assert_eq!(clean(b"a\x1b]8;;http://x\nb"), ("a".to_owned(), 1));
+    }
+
+    #[test]
+    fn other_escapes_and_controls_are_removed() {
+        // An nF sequence (ESC SP F), a two-byte escape, CR, BS and DEL.
+        assert_eq!(clean(b"a\x1b Fb\x1bcc\rd\x08e\x7f"), ("abcde".to_owned(), 5));
+        assert_eq!(clean(b"trailing\x1b"), ("trailing".to_owned(), 1));
+    }
+
+    #[test]
+    fn bidi_overrides_and_isolates_are_removed() {
+        // RLO, PDF, LRI, PDI: "rm -rf ~" displayed as "ls".
+        let text = "echo \u{202e}sl\u{202c}\u{2066}; rm -rf ~\u{2069}";
+        assert_eq!(clean(text.as_bytes()), ("echo sl; rm -rf ~".to_owned(), 4));
+        let rest = "\u{202a}\u{202b}\u{202d}\u{2067}\u{2068}";
+        assert_eq!(clean(rest.as_bytes()), (String::new(), 5));
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        assert_eq!(clean(b"a\xffb"), ("a\u{fffd}b".to_owned(), 0));
+    }
+}
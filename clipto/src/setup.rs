@@ -0,0 +1,301 @@
+//! `clipto setup`: guided first run. Each step checks what is already in
+//! place, asks before changing anything, and can be re-run safely.
+
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+use clipto_client::CopyOptions;
+use clipto_ipc::Request;
+
+use crate::{client, integrate, request};
+
+const TMUX_BINDINGS: &str = "\
+bind -T copy-mode-vi y send -X copy-pipe-and-cancel \"clipto copy\"
+bind P run \"clipto paste | tmux load-buffer - && tmux paste-buffer\"
+";
+
+struct Wizard {
+    /// Accept every default without asking.
+    yes: bool,
+}
+
+pub fn run(yes: bool) -> Result<()> {
+    if cfg!(target_os = "macos") {
+        bail!("clipto setup drives systemd; on macOS follow the launchd steps in the README");
+    }
+    let wizard = Wizard { yes };
+    let config = config_dir()?;
+
+    step("Encryption key");
+    wizard.key(&config.join("clipto-key.cred"))?;
+
+    step("systemd user service");
+    wizard.service(&config.join("clipto-key.cred"))?;
+
+    step("tmux bindings");
+    wizard.tmux()?;
+
+    step("Neovim integration");
+    wizard.nvim()?;
+
+    step("Emacs integration");
+    wizard.emacs()?;
+
+    step("End-to-end test");
+    end_to_end()
+}
+
+impl Wizard {
+    fn key(&self, cred: &Path) -> Result<()> {
+        if cred.exists() {
+            println!("  {} already exists, keeping it", cred.display());
+            return Ok(());
+        }
+        if !self.confirm(&format!("Generate a key sealed to {}?", cred.display()), true)? {
+            return Ok(());
+        }
+        fs::create_dir_all(cred.parent().unwrap())?;
+
+        let mut key = [0u8; 32];
+        fs::File::open("/dev/urandom")?.read_exact(&mut key)?;
+        let mut child = Command::new("systemd-creds")
+            .args(["encrypt", "--name=clipto-key", "-"])
+            .arg(cred)
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("failed to run systemd-creds")?;
+        child.stdin.take().unwrap().write_all(&key)?;
+        key.fill(0);
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("systemd-creds encrypt exited with {status}");
+        }
+        println!("  sealed a new key to {}", cred.display());
+        Ok(())
+    }
+
+    fn service(&self, cred: &Path) -> Result<()> {
+        let exe = std::env::current_exe().context("failed to locate the clipto executable")?;
+        let unit = unit(&exe, cred);
+        let path = home()?.join(".config/systemd/user/clipd.service");
+
+        match fs::read_to_string(&path) {
+            Ok(existing) if existing == unit => println!("  {} is up to date", path.display()),
+            Ok(_) if !self.confirm(&format!("Overwrite {}?", path.display()), false)? => {
+                return Ok(());
+            }
+            Err(_) if !self.confirm(&format!("Install {}?", path.display()), true)? => {
+                return Ok(());
+            }
+            _ => {
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::write(&path, &unit)?;
+                println!("  wrote {}", path.display());
+            }
+        }
+
+        if self.confirm("Enable and (re)start clipd now?", true)? {
+            systemctl(&["daemon-reload"])?;
+            systemctl(&["enable", "clipd"])?;
+            systemctl(&["restart", "clipd"])?;
+            println!("  clipd enabled and started");
+        }
+        Ok(())
+    }
+
+    fn tmux(&self) -> Result<()> {
+        let Some(conf) = tmux_conf()? else {
+            println!("  no tmux.conf found; add these bindings to yours:\n");
+            print!("{TMUX_BINDINGS}");
+            return Ok(());
+        };
+        let existing = fs::read_to_string(&conf).unwrap_or_default();
+        if existing.contains("clipto copy") {
+            println!("  {} already uses clipto", conf.display());
+            return Ok(());
+        }
+        print!("\n{TMUX_BINDINGS}\n");
+        if !self.confirm(&format!("Append these bindings to {}?", conf.display()), true)? {
+            return Ok(());
+        }
+        let mut file = fs::OpenOptions::new().append(true).open(&conf)?;
+        write!(file, "\n# clipto\n{TMUX_BINDINGS}")?;
+        println!("  updated {}; reload with `tmux source-file {}`", conf.display(), conf.display());
+        Ok(())
+    }
+
+    fn nvim(&self) -> Result<()> {
+        let dir = home()?.join(".config/nvim");
+        if !dir.is_dir() {
+            println!("  no ~/.config/nvim; see `clipto integrate nvim` if you use Neovim");
+            return Ok(());
+        }
+        let path = dir.join("plugin/clipto.lua");
+        if path.exists() {
+            println!("  {} already exists", path.display());
+            return Ok(());
+        }
+        if !self.confirm(&format!("Write {}?", path.display()), true)? {
+            return Ok(());
+        }
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, integrate::nvim())?;
+        println!("  wrote {}", path.display());
+        Ok(())
+    }
+
+    fn emacs(&self) -> Result<()> {
+        let dir = home()?.join(".config/emacs");
+        if !dir.is_dir() {
+            println!("  no ~/.config/emacs; see `clipto integrate emacs` if you use Emacs");
+            return Ok(());
+        }
+        let path = dir.join("clipto.el");
+        if path.exists() {
+            println!("  {} already exists", path.display());
+            return Ok(());
+        }
+        if !self.confirm(&format!("Write {}?", path.display()), false)? {
+            return Ok(());
+        }
+        fs::write(&path, integrate::emacs(&clipto_ipc::line_socket_path()?))?;
+        println!("  wrote {}; it needs `line_socket = true` in config.toml", path.display());
+        Ok(())
+    }
+
+    fn confirm(&self, question: &str, default: bool) -> Result<bool> {
+        if self.yes {
+            return Ok(default);
+        }
+        let hint = if default { "[Y/n]" } else { "[y/N]" };
+        print!("  {question} {hint} ");
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(match answer.trim().to_ascii_lowercase().as_str() {
+            "" => default,
+            a => a.starts_with('y'),
+        })
+    }
+}
+
+/// Copy a marker through the daemon (and, in a Wayland session, through the
+/// compositor in both directions) and check it comes back unchanged.
+fn end_to_end() -> Result<()> {
+    wait_for_daemon()?;
+
+    let marker = format!("clipto setup {}", std::process::id());
+    copy(&marker)?;
+    check("daemon paste", &paste()?, &marker)?;
+
+    if std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        println!("  no Wayland session, skipping compositor checks");
+        return Ok(());
+    }
+    check("wl-paste after clipto copy", &wl_paste()?, &marker)?;
+
+    let back = format!("{marker} from wayland");
+    let status = Command::new("wl-copy").arg(&back).status().context("failed to run wl-copy")?;
+    if !status.success() {
+        bail!("wl-copy exited with {status}");
+    }
+    // The watcher syncs asynchronously.
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while paste()? != back && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    check("clipto paste after wl-copy", &paste()?, &back)
+}
+
+fn wait_for_daemon() -> Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match request(&Request::Stats) {
+            Ok(_) => return Ok(()),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => std::thread::sleep(Duration::from_millis(200)),
+        }
+    }
+}
+
+fn copy(text: &str) -> Result<()> {
+    client()?.copy(text.as_bytes().to_vec(), CopyOptions::default())
+}
+
+fn paste() -> Result<String> {
+    Ok(String::from_utf8_lossy(&client()?.paste()?).into_owned())
+}
+
+fn wl_paste() -> Result<String> {
+    let out = Command::new("wl-paste")
+        .arg("--no-newline")
+        .output()
+        .context("failed to run wl-paste")?;
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+fn check(what: &str, got: &str, want: &str) -> Result<()> {
+    if got != want {
+        bail!("{what}: expected {want:?}, got {got:?}");
+    }
+    println!("  {what}: ok");
+    Ok(())
+}
+
+fn step(title: &str) {
+    println!("\n==> {title}");
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .context("failed to run systemctl")?;
+    if !status.success() {
+        bail!("systemctl --user {} exited with {status}", args.join(" "));
+    }
+    Ok(())
+}
+
+/// The shipped `contrib/clipd.service`, started with the graphical session
+/// so `WAYLAND_DISPLAY` is set, running `exe` with the key sealed at `cred`.
+fn unit(exe: &Path, cred: &Path) -> String {
+    include_str!("../../contrib/clipd.service")
+        .lines()
+        .map(|line| {
+            if line.starts_with("ExecStart=") {
+                format!("ExecStart={} daemon --foreground\n", exe.display())
+            } else if line.starts_with("LoadCredentialEncrypted=") {
+                format!("LoadCredentialEncrypted=clipto-key:{}\n", cred.display())
+            } else {
+                format!("{line}\n")
+            }
+        })
+        .collect()
+}
+
+/// The user's tmux config: `~/.config/tmux/tmux.conf`, else `~/.tmux.conf`,
+/// whichever exists.
+fn tmux_conf() -> Result<Option<PathBuf>> {
+    let home = home()?;
+    let candidates = [home.join(".config/tmux/tmux.conf"), home.join(".tmux.conf")];
+    Ok(candidates.into_iter().find(|p| p.exists()))
+}
+
+fn config_dir() -> Result<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir).join("clipto")),
+        None => Ok(home()?.join(".config/clipto")),
+    }
+}
+
+fn home() -> Result<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from).context("$HOME is not set")
+}
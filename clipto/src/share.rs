@@ -0,0 +1,89 @@
+//! `clipto share` / `clipto receive`: one entry, encrypted to someone else's
+//! key, as ASCII armor that survives a chat message.
+//!
+//! The armor holds the raw entry, so the other side can also decrypt it with
+//! plain `age -d` or `gpg -d`. age recipients (`age1…`) are handled in
+//! process; anything else is taken as a GPG key and handed to `gpg`, through
+//! pipes, so the plaintext is never written to disk either way.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use clipto_client::CopyOptions;
+use clipto_ipc::{Request, Response};
+
+use crate::{archive, client, request, write_atomic};
+
+const PGP_ARMOR: &[u8] = b"-----BEGIN PGP MESSAGE-----";
+
+/// Encrypt history entry `index` to `recipients`, writing the armor to
+/// `output` or stdout.
+pub fn share(index: u32, recipients: &[String], output: Option<&Path>) -> Result<()> {
+    let gpg = recipients.iter().filter(|r| !r.starts_with("age1")).count();
+    if gpg != 0 && gpg != recipients.len() {
+        bail!("can't mix age and GPG recipients");
+    }
+    let plaintext = match request(&Request::Entry { index })? {
+        Response::Payload { data } => data,
+        Response::Error { message } => bail!("clipd: {message}"),
+        _ => bail!("clipd: unexpected response to Entry"),
+    };
+
+    let armor = if gpg == 0 {
+        archive::encrypt(&plaintext, recipients, true)?
+    } else {
+        let mut args = vec!["--encrypt", "--armor"];
+        for r in recipients {
+            args.extend(["--recipient", r]);
+        }
+        run_gpg(&args, plaintext)?
+    };
+    match output {
+        Some(path) => write_atomic(path, &armor),
+        None => io::stdout().write_all(&armor).context("failed to write to stdout"),
+    }
+}
+
+/// Decrypt what `share` wrote, from `input` or stdin, and copy it.
+pub fn receive(input: Option<&Path>, identities: &[PathBuf]) -> Result<()> {
+    let armor = match input {
+        Some(path) => fs::read(path).with_context(|| format!("failed to read {}", path.display()))?,
+        None => {
+            let mut armor = Vec::new();
+            io::stdin().read_to_end(&mut armor).context("failed to read stdin")?;
+            armor
+        }
+    };
+    let plaintext = if armor.trim_ascii_start().starts_with(PGP_ARMOR) {
+        run_gpg(&["--decrypt", "--quiet"], armor)?
+    } else {
+        archive::decrypt(&armor, identities, input.unwrap_or(Path::new("stdin")))?
+    };
+    client()?.copy(plaintext, CopyOptions::default())
+}
+
+/// Run `gpg` with `input` on stdin and return its stdout. Prompts (a
+/// passphrase, trusting a key) still reach the terminal.
+fn run_gpg(args: &[&str], input: Vec<u8>) -> Result<Vec<u8>> {
+    let mut child = Command::new("gpg")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("failed to run gpg")?;
+    // From another thread, so a large output can't fill its pipe while
+    // gpg waits on a full stdin.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let out = child.wait_with_output().context("failed to run gpg")?;
+    let written = writer.join().expect("gpg writer panicked");
+    if !out.status.success() {
+        bail!("gpg exited with {}", out.status);
+    }
+    written.context("failed to write to gpg")?;
+    Ok(out.stdout)
+}
@@ -0,0 +1,108 @@
+//! `clipto shell-init`: completions plus line-editor widgets, printed for
+//! one `eval` in the shell's rc file.
+//!
+//! The widgets have stable names, so users can bind them to other keys:
+//! `clipto-copy-line` copies the command line, `clipto-paste` inserts the
+//! clipboard at the cursor and `clipto-pick` opens `clipto pick` and
+//! inserts what was picked. Pastes go through `--sanitize`: text inserted
+//! into the line editor is one Enter away from running.
+
+use std::io::{self, Write};
+
+use anyhow::{Context, Result};
+use clap::Command;
+use clap_complete::Shell;
+
+use crate::InitShell;
+
+const BASH: &str = r#"
+# clipto line-editor widgets. Generated by `clipto shell-init bash`.
+# Alt-W copies the command line, Ctrl-X Ctrl-V pastes at the cursor,
+# Alt-V picks a history entry and pastes it.
+
+clipto-copy-line() {
+  printf '%s' "$READLINE_LINE" | clipto copy
+}
+
+clipto-paste() {
+  local text
+  text=$(clipto paste --sanitize 2>/dev/null) || return
+  READLINE_LINE=${READLINE_LINE:0:READLINE_POINT}$text${READLINE_LINE:READLINE_POINT}
+  READLINE_POINT=$((READLINE_POINT + ${#text}))
+}
+
+clipto-pick() {
+  clipto pick </dev/tty >/dev/tty && clipto-paste
+}
+
+bind -x '"\ew": clipto-copy-line'
+bind -x '"\C-x\C-v": clipto-paste'
+bind -x '"\ev": clipto-pick'
+"#;
+
+const ZSH: &str = r#"
+# clipto line-editor widgets. Generated by `clipto shell-init zsh`.
+# Alt-W copies the command line, Ctrl-X Ctrl-V pastes at the cursor,
+# Alt-V picks a history entry and pastes it.
+
+clipto-copy-line() {
+  print -rn -- "$BUFFER" | clipto copy && zle -M "clipto: copied the command line"
+}
+
+clipto-paste() {
+  local text
+  text=$(clipto paste --sanitize 2>/dev/null) || return
+  LBUFFER+=$text
+}
+
+clipto-pick() {
+  clipto pick </dev/tty >/dev/tty && zle clipto-paste
+  zle reset-prompt
+}
+
+zle -N clipto-copy-line
+zle -N clipto-paste
+zle -N clipto-pick
+bindkey '^[w' clipto-copy-line
+bindkey '^X^V' clipto-paste
+bindkey '^[v' clipto-pick
+"#;
+
+const FISH: &str = r#"
+# clipto line-editor widgets. Generated by `clipto shell-init fish`.
+# Alt-W copies the command line, Ctrl-X Ctrl-V pastes at the cursor,
+# Alt-V picks a history entry and pastes it.
+
+function clipto-copy-line
+    commandline | clipto copy
+end
+
+function clipto-paste
+    set -l text (clipto paste --sanitize 2>/dev/null | string collect); or return
+    commandline -i -- $text
+end
+
+function clipto-pick
+    clipto pick </dev/tty >/dev/tty; and clipto-paste
+    commandline -f repaint
+end
+
+bind \ew clipto-copy-line
+bind \cx\cv clipto-paste
+bind \ev clipto-pick
+"#;
+
+/// Print `command`'s completions for `shell`, then the widgets.
+pub fn init(shell: InitShell, command: &mut Command) -> Result<()> {
+    let (shell, widgets) = match shell {
+        InitShell::Bash => (Shell::Bash, BASH),
+        InitShell::Zsh => (Shell::Zsh, ZSH),
+        InitShell::Fish => (Shell::Fish, FISH),
+    };
+    // Into a buffer first: clap_complete panics on write errors, such as
+    // a closed pipe.
+    let mut script = Vec::new();
+    clap_complete::generate(shell, command, "clipto", &mut script);
+    script.extend_from_slice(widgets.as_bytes());
+    io::stdout().write_all(&script).context("failed to write to stdout")
+}
@@ -0,0 +1,33 @@
+//! `clipto wayland-sync`: run by clipd's `wl-paste --watch` when MIME
+//! preferences are configured. Picks one of the offered types and sends that
+//! representation to the daemon.
+
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+/// Fetch the compositor clipboard in the type chosen from `prefer`/`deny`,
+/// returning the type and the content. `None` means nothing acceptable was
+/// offered.
+pub fn fetch(prefer: &[String], deny: &[String]) -> Result<Option<(String, Vec<u8>)>> {
+    let offered = wl_paste(&["--list-types"])?;
+    let offered = String::from_utf8_lossy(&offered);
+    let offered: Vec<&str> = offered.lines().map(str::trim).filter(|t| !t.is_empty()).collect();
+
+    let Some(mime) = clipto_ipc::choose_type(&offered, prefer, deny) else {
+        return Ok(None);
+    };
+    let data = wl_paste(&["--no-newline", "--type", mime])?;
+    Ok(Some((mime.to_owned(), data)))
+}
+
+fn wl_paste(args: &[&str]) -> Result<Vec<u8>> {
+    let out = Command::new("wl-paste")
+        .args(args)
+        .output()
+        .context("failed to run wl-paste")?;
+    if !out.status.success() {
+        bail!("wl-paste {} exited with {}", args.join(" "), out.status);
+    }
+    Ok(out.stdout)
+}